@@ -2,64 +2,152 @@ extern crate proc_macro;
 
 use std::ops::Index;
 
-use proc_macro::TokenStream;
+use embedded_gfx::mesh::{Geometry, OwnedGeometry};
+use proc_macro::{TokenStream, TokenTree};
 
+/// Embeds an stl file as a `Geometry`.
+///
+/// Options can follow the path:
+/// - `smooth_normals` generates smooth vertex normals
+/// - `smooth_normals = 30` also keeps hard edges where faces meet at more than 30 degrees
+/// - `recompute_normals` derives the face normals from the winding instead of the file
 #[proc_macro]
 pub fn embed_stl(input: TokenStream) -> TokenStream {
-    let file_path = input.to_string();
+    let mut tokens = input.into_iter().peekable();
 
-    let r = load_stl(file_path.trim_matches('"'));
+    let file_path = match tokens.next() {
+        Some(TokenTree::Literal(literal)) => literal.to_string(),
+        _ => panic!("Expected the path of an stl file"),
+    };
+
+    let mut options = Options::default();
+
+    while let Some(token) = tokens.next() {
+        let TokenTree::Ident(option) = token else {
+            continue;
+        };
+
+        // an optional `= value`
+        let value = match tokens.peek() {
+            Some(TokenTree::Punct(punct)) if punct.as_char() == '=' => {
+                tokens.next();
+                match tokens.next() {
+                    Some(TokenTree::Literal(literal)) => Some(
+                        literal
+                            .to_string()
+                            .parse::<f32>()
+                            .unwrap_or_else(|_| panic!("Invalid value for `{option}`")),
+                    ),
+                    _ => panic!("Expected a value for `{option}`"),
+                }
+            }
+            _ => None,
+        };
+
+        match option.to_string().as_str() {
+            "smooth_normals" => {
+                options.smooth_normals = true;
+                options.crease_angle = value.map(f32::to_radians);
+            }
+            "recompute_normals" => options.recompute_normals = true,
+            other => panic!("Unknown option `{other}`"),
+        }
+    }
+
+    let r = load_stl(file_path.trim_matches('"'), &options);
 
     r.parse().unwrap()
 }
 
-fn load_stl(file_name: &str) -> String {
+#[derive(Default)]
+struct Options {
+    smooth_normals: bool,
+    crease_angle: Option<f32>,
+    recompute_normals: bool,
+}
+
+fn load_stl(file_name: &str, options: &Options) -> String {
     let mut file = std::fs::OpenOptions::new()
         .read(true)
         .open(file_name)
         .unwrap();
     let stl = stl_io::read_stl(&mut file).unwrap();
 
-    let mut vertices = String::new();
-    for vertex in stl.vertices {
-        vertices += &format!(
-            "[{}f32,{}f32,{}f32],",
-            vertex.index(0),
-            vertex.index(1),
-            vertex.index(2)
-        );
-    }
+    let mut geometry = OwnedGeometry {
+        vertices: stl
+            .vertices
+            .iter()
+            .map(|v| [*v.index(0), *v.index(1), *v.index(2)])
+            .collect(),
+        faces: stl
+            .faces
+            .iter()
+            .map(|f| [f.vertices[0], f.vertices[1], f.vertices[2]])
+            .collect(),
+        normals: stl
+            .faces
+            .iter()
+            .map(|f| [*f.normal.index(0), *f.normal.index(1), *f.normal.index(2)])
+            .collect(),
+        ..Default::default()
+    };
 
-    let mut faces = String::new();
-    for triangle in &stl.faces {
-        faces += &format!(
-            "[{},{},{}],",
-            triangle.vertices[0], triangle.vertices[1], triangle.vertices[2]
-        );
+    if options.recompute_normals {
+        geometry.recompute_normals();
     }
 
-    let mut normals = String::new();
-    for triangle in &stl.faces {
-        normals += &format!(
-            "[{}f32,{}f32,{}f32],",
-            triangle.normal.index(0),
-            triangle.normal.index(1),
-            triangle.normal.index(2)
-        );
+    // before smoothing, which may split vertices along hard edges
+    geometry.lines = Geometry::lines_from_faces(&geometry.faces)
+        .into_iter()
+        .map(|(a, b)| [a, b])
+        .collect();
+
+    if options.smooth_normals {
+        geometry.generate_vertex_normals(options.crease_angle);
     }
 
-    let lines = embedded_gfx::mesh::Geometry::lines_from_faces(
-        &stl.faces
-            .iter()
-            .map(|f| [f.vertices[0], f.vertices[1], f.vertices[2]])
-            .collect::<Vec<_>>(),
-    );
+    geometry.update_bounds();
+
+    let vertices = format_floats(&geometry.vertices);
+    let normals = format_floats(&geometry.normals);
+    let vertex_normals = format_floats(&geometry.vertex_normals);
+
+    let mut faces = String::new();
+    for face in &geometry.faces {
+        faces += &format!("[{},{},{}],", face[0], face[1], face[2]);
+    }
 
     let mut lines_ = String::new();
-    for line in lines {
-        lines_ += &format!("[{},{}],", line.0, line.1);
+    for line in &geometry.lines {
+        lines_ += &format!("[{},{}],", line[0], line[1]);
     }
 
+    let bounds = match geometry.bounds {
+        Some(bounds) => format!(
+            "Some(embedded_gfx::bounds::Bounds {{
+            aabb: embedded_gfx::bounds::Aabb {{
+                min: [{}f32,{}f32,{}f32],
+                max: [{}f32,{}f32,{}f32],
+            }},
+            sphere: embedded_gfx::bounds::BoundingSphere {{
+                center: [{}f32,{}f32,{}f32],
+                radius: {}f32,
+            }},
+        }})",
+            bounds.aabb.min[0],
+            bounds.aabb.min[1],
+            bounds.aabb.min[2],
+            bounds.aabb.max[0],
+            bounds.aabb.max[1],
+            bounds.aabb.max[2],
+            bounds.sphere.center[0],
+            bounds.sphere.center[1],
+            bounds.sphere.center[2],
+            bounds.sphere.radius
+        ),
+        None => "None".to_string(),
+    };
+
     let mut ret: String = String::new();
 
     ret += &format!(
@@ -77,8 +165,23 @@ fn load_stl(file_name: &str) -> String {
         normals: &[
             {normals}
         ],
+        vertex_normals: &[
+            {vertex_normals}
+        ],
+        uvs: &[],
+        material_indices: &[],
+        bounds: {bounds},
     }}"
     );
 
     ret
 }
+
+fn format_floats(values: &[[f32; 3]]) -> String {
+    let mut ret = String::new();
+    for value in values {
+        ret += &format!("[{}f32,{}f32,{}f32],", value[0], value[1], value[2]);
+    }
+
+    ret
+}
@@ -0,0 +1,174 @@
+use nalgebra::{Matrix4, Vector3, Vector4};
+
+/// Axis aligned box in model space
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingSphere {
+    pub center: [f32; 3],
+    pub radius: f32,
+}
+
+/// Bounding volumes of a mesh in model space, used to skip meshes that are entirely off screen
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bounds {
+    pub aabb: Aabb,
+    pub sphere: BoundingSphere,
+}
+
+impl Bounds {
+    /// Returns None if there are no vertices
+    pub fn from_vertices(vertices: &[[f32; 3]]) -> Option<Bounds> {
+        let first = *vertices.first()?;
+        let mut min = first;
+        let mut max = first;
+
+        for vertex in vertices {
+            for axis in 0..3 {
+                min[axis] = min[axis].min(vertex[axis]);
+                max[axis] = max[axis].max(vertex[axis]);
+            }
+        }
+
+        let center = (Vector3::from(min) + Vector3::from(max)) / 2.0;
+
+        // tighter than half the diagonal of the box
+        let radius = libm::sqrtf(
+            vertices
+                .iter()
+                .map(|v| (Vector3::from(*v) - center).norm_squared())
+                .fold(0.0, f32::max),
+        );
+
+        Some(Bounds {
+            aabb: Aabb { min, max },
+            sphere: BoundingSphere {
+                center: center.into(),
+                radius,
+            },
+        })
+    }
+}
+
+/// Left, right, bottom, top, near and far planes, with the normals pointing inside
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frustum {
+    pub planes: [Vector4<f32>; 6],
+}
+
+impl Frustum {
+    /// Extracts the planes of a projection matrix, in the space the matrix transforms from.
+    ///
+    /// Passing `vp_matrix * model_matrix` gives the planes in the model space of a mesh.
+    pub fn from_matrix(matrix: &Matrix4<f32>) -> Frustum {
+        let row = |i: usize| matrix.row(i).transpose();
+
+        let planes = [
+            row(3) + row(0),
+            row(3) - row(0),
+            row(3) + row(1),
+            row(3) - row(1),
+            row(3) + row(2),
+            row(3) - row(2),
+        ]
+        .map(|plane| {
+            let length = plane.xyz().norm();
+
+            if length > f32::EPSILON {
+                plane / length
+            } else {
+                plane
+            }
+        });
+
+        Frustum { planes }
+    }
+
+    /// Stops rejecting anything beyond the far plane, for when far clipping is disabled
+    pub fn without_far_plane(mut self) -> Frustum {
+        self.planes[5] = Vector4::zeros();
+        self
+    }
+
+    pub fn intersects_sphere(&self, sphere: &BoundingSphere) -> bool {
+        let center = Vector4::new(sphere.center[0], sphere.center[1], sphere.center[2], 1.0);
+
+        self.planes
+            .iter()
+            .all(|plane| plane.dot(&center) >= -sphere.radius)
+    }
+
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|plane| {
+            // the corner furthest along the plane normal
+            let corner = Vector4::new(
+                if plane.x >= 0.0 {
+                    aabb.max[0]
+                } else {
+                    aabb.min[0]
+                },
+                if plane.y >= 0.0 {
+                    aabb.max[1]
+                } else {
+                    aabb.min[1]
+                },
+                if plane.z >= 0.0 {
+                    aabb.max[2]
+                } else {
+                    aabb.min[2]
+                },
+                1.0,
+            );
+
+            plane.dot(&corner) >= 0.0
+        })
+    }
+
+    /// Conservative test, some meshes just outside the corners of the frustum are kept
+    pub fn intersects(&self, bounds: &Bounds) -> bool {
+        self.intersects_sphere(&bounds.sphere) && self.intersects_aabb(&bounds.aabb)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Point3;
+
+    use super::*;
+    use crate::camera::Camera;
+
+    // a cube with a side of 2 around `center`
+    fn cube_at(center: [f32; 3]) -> Bounds {
+        let corners: [[f32; 3]; 8] = core::array::from_fn(|i| {
+            core::array::from_fn(|axis| {
+                center[axis] + if i & (1 << axis) != 0 { 1.0 } else { -1.0 }
+            })
+        });
+
+        Bounds::from_vertices(&corners).unwrap()
+    }
+
+    #[test]
+    fn frustum_rejects_meshes_outside_of_the_view() {
+        // looking down -z, with the far plane at 20
+        let mut camera = Camera::new(1.0);
+        camera.set_target(Point3::new(0.0, 0.0, -1.0));
+        let frustum = Frustum::from_matrix(&camera.vp_matrix);
+
+        assert!(frustum.intersects(&cube_at([0.0, 0.0, -5.0])));
+        assert!(!frustum.intersects(&cube_at([0.0, 0.0, 5.0])), "behind");
+        assert!(!frustum.intersects(&cube_at([20.0, 0.0, -5.0])), "beside");
+        assert!(
+            !frustum.intersects(&cube_at([0.0, 0.0, -30.0])),
+            "beyond far"
+        );
+
+        let frustum = frustum.without_far_plane();
+        assert!(frustum.intersects(&cube_at([0.0, 0.0, -30.0])));
+        assert!(!frustum.intersects(&cube_at([0.0, 0.0, 5.0])));
+    }
+}
@@ -1,16 +1,40 @@
-use std::f32::consts;
+use core::f32::consts;
 
-use nalgebra::{Isometry3, Perspective3, Point3, Vector3};
+use nalgebra::{
+    Isometry3, Orthographic3, Perspective3, Point3, Translation3, Unit, UnitQuaternion, Vector3,
+    Vector4,
+};
+
+use crate::bounds::Frustum;
+use crate::ray::Ray;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Projection {
+    Perspective,
+    /// View volume bounds in camera space, `near` and `far` are shared with the perspective mode
+    Orthographic {
+        left: f32,
+        right: f32,
+        bottom: f32,
+        top: f32,
+    },
+}
 
 pub struct Camera {
     pub position: Point3<f32>,
+    projection: Projection,
     fov: f32,
     pub near: f32,
     pub far: f32,
     view_matrix: nalgebra::Matrix4<f32>,
     projection_matrix: nalgebra::Matrix4<f32>,
     pub vp_matrix: nalgebra::Matrix4<f32>,
-    target: Point3<f32>,
+    // rotation from camera space to world space, the camera looks down its local -z axis
+    orientation: UnitQuaternion<f32>,
+    up: Unit<Vector3<f32>>,
+    roll: f32,
+    // while set, the camera keeps looking at the target when it moves
+    target: Option<Point3<f32>>,
     aspect_ratio: f32,
 }
 
@@ -18,11 +42,15 @@ impl Camera {
     pub fn new(aspect_ratio: f32) -> Camera {
         let mut ret = Camera {
             position: Point3::new(0.0, 0.0, 0.0),
+            projection: Projection::Perspective,
             fov: consts::PI / 2.0,
             view_matrix: nalgebra::Matrix4::identity(),
             projection_matrix: nalgebra::Matrix4::identity(),
             vp_matrix: nalgebra::Matrix4::identity(),
-            target: Point3::new(0.0, 0.0, 0.0),
+            orientation: UnitQuaternion::identity(),
+            up: Vector3::y_axis(),
+            roll: 0.0,
+            target: Some(Point3::new(0.0, 0.0, 0.0)),
             aspect_ratio,
             near: 0.4,
             far: 20.0,
@@ -45,8 +73,147 @@ impl Camera {
         self.update_projection();
     }
 
+    pub fn set_projection(&mut self, projection: Projection) {
+        self.projection = projection;
+
+        self.update_projection();
+    }
+
+    /// Orthographic projection centered on the view axis, `height` is in world units
+    pub fn set_orthographic(&mut self, height: f32) {
+        let half_height = height / 2.0;
+        let half_width = half_height * self.aspect_ratio;
+
+        self.set_projection(Projection::Orthographic {
+            left: -half_width,
+            right: half_width,
+            bottom: -half_height,
+            top: half_height,
+        });
+    }
+
+    pub fn set_perspective(&mut self) {
+        self.set_projection(Projection::Perspective);
+    }
+
+    pub fn get_projection(&self) -> Projection {
+        self.projection
+    }
+
+    pub fn is_orthographic(&self) -> bool {
+        matches!(self.projection, Projection::Orthographic { .. })
+    }
+
+    /// Keeps the camera pointed at `target`, until an explicit orientation is set
     pub fn set_target(&mut self, target: Point3<f32>) {
-        self.target = target;
+        self.target = Some(target);
+        self.update_view();
+    }
+
+    /// Moves the camera and points it at `target`, updating the view once
+    pub fn look_at(&mut self, position: Point3<f32>, target: Point3<f32>) {
+        self.position = position;
+        self.target = Some(target);
+        self.update_view();
+    }
+
+    pub fn get_target(&self) -> Option<Point3<f32>> {
+        self.target
+    }
+
+    /// Reference up vector used when aiming the camera, for yaw and for orbiting, defaults to +y
+    pub fn set_up(&mut self, up: Vector3<f32>) {
+        if let Some(up) = Unit::try_new(up, f32::EPSILON) {
+            self.up = up;
+            self.update_view();
+        }
+    }
+
+    pub fn get_up(&self) -> Vector3<f32> {
+        self.up.into_inner()
+    }
+
+    /// Rotation around the view axis in radians, positive values bank to the right
+    pub fn set_roll(&mut self, roll: f32) {
+        self.orientation *= roll_rotation(roll - self.roll);
+        self.roll = roll;
+        self.update_view();
+    }
+
+    pub fn get_roll(&self) -> f32 {
+        self.roll
+    }
+
+    pub fn roll(&mut self, angle: f32) {
+        self.set_roll(self.roll + angle);
+    }
+
+    /// Absolute orientation relative to the up vector, in radians.
+    ///
+    /// With all angles at zero the camera looks down -z, positive yaw turns right and positive
+    /// pitch looks up. This stops tracking the target.
+    pub fn set_yaw_pitch_roll(&mut self, yaw: f32, pitch: f32, roll: f32) {
+        // yaw and pitch are built around +y, then the whole frame is aligned with the up vector
+        self.orientation = align_up(&self.up)
+            * UnitQuaternion::from_axis_angle(&Vector3::y_axis(), -yaw)
+            * UnitQuaternion::from_axis_angle(&Vector3::x_axis(), pitch)
+            * roll_rotation(roll);
+        self.roll = roll;
+        self.target = None;
+        self.update_view();
+    }
+
+    /// Sets the camera to world rotation directly, this stops tracking the target
+    pub fn set_orientation(&mut self, orientation: UnitQuaternion<f32>) {
+        self.orientation = orientation;
+        self.roll = 0.0;
+        self.target = None;
+        self.update_view();
+    }
+
+    /// Moves the camera and sets its orientation, updating the view once
+    pub fn set_pose(&mut self, position: Point3<f32>, orientation: UnitQuaternion<f32>) {
+        self.position = position;
+        self.set_orientation(orientation);
+    }
+
+    pub fn get_orientation(&self) -> UnitQuaternion<f32> {
+        self.orientation
+    }
+
+    /// Unit vector the camera is looking along
+    pub fn forward(&self) -> Vector3<f32> {
+        self.orientation * -Vector3::z()
+    }
+
+    pub fn right(&self) -> Vector3<f32> {
+        self.orientation * Vector3::x()
+    }
+
+    /// The camera's own up axis, which differs from the reference up vector when pitched or rolled
+    pub fn up(&self) -> Vector3<f32> {
+        self.orientation * Vector3::y()
+    }
+
+    /// Moves along the view direction, the target moves along with the camera
+    pub fn move_forward(&mut self, distance: f32) {
+        self.translate(self.forward() * distance);
+    }
+
+    /// Moves sideways, positive values move to the right
+    pub fn strafe(&mut self, distance: f32) {
+        self.translate(self.right() * distance);
+    }
+
+    pub fn move_up(&mut self, distance: f32) {
+        self.translate(self.up() * distance);
+    }
+
+    pub fn translate(&mut self, offset: Vector3<f32>) {
+        self.position += offset;
+        if let Some(target) = &mut self.target {
+            *target += offset;
+        }
         self.update_view();
     }
 
@@ -56,16 +223,132 @@ impl Camera {
         Vector3::new(transpose[(2, 0)], transpose[(2, 1)], transpose[(2, 2)])
     }
 
+    /// View frustum planes in world space
+    pub fn frustum(&self) -> Frustum {
+        Frustum::from_matrix(&self.vp_matrix)
+    }
+
+    /// World space ray through the center of pixel (`x`, `y`) of a `width` x `height` screen.
+    ///
+    /// The ray starts on the near plane, returns None if the view projection is degenerate.
+    pub fn unproject(&self, x: f32, y: f32, width: u16, height: u16) -> Option<Ray> {
+        let inverse = self.vp_matrix.try_inverse()?;
+
+        let ndc_x = (x + 0.5) / width as f32 * 2.0 - 1.0;
+        let ndc_y = 1.0 - (y + 0.5) / height as f32 * 2.0;
+
+        let unproject = |z: f32| {
+            let point = inverse * Vector4::new(ndc_x, ndc_y, z, 1.0);
+            Point3::from(point.xyz() / point.w)
+        };
+
+        let near = unproject(-1.0);
+        let far = unproject(1.0);
+
+        Some(Ray {
+            origin: near,
+            direction: (far - near).try_normalize(f32::EPSILON)?,
+        })
+    }
+
+    /// Vector from `point` towards the viewer, not normalized.
+    ///
+    /// With an orthographic projection every point is viewed along the same direction.
+    pub fn view_vector(&self, point: &Point3<f32>) -> Vector3<f32> {
+        match self.projection {
+            Projection::Perspective => self.position - point,
+            Projection::Orthographic { .. } => self.get_direction(),
+        }
+    }
+
+    fn aim_at(&mut self, target: Point3<f32>) {
+        let forward = target - self.position;
+
+        if forward.norm_squared() <= f32::EPSILON {
+            return;
+        }
+
+        if forward.normalize().cross(&self.up).norm_squared() > 1e-6 {
+            self.orientation =
+                UnitQuaternion::face_towards(&-forward, &self.up) * roll_rotation(self.roll);
+        } else if let Some(rotation) = UnitQuaternion::rotation_between(&self.forward(), &forward) {
+            // looking along the up vector, turn as little as possible so the roll is kept
+            self.orientation = rotation * self.orientation;
+        }
+    }
+
     fn update_view(&mut self) {
-        let view = Isometry3::look_at_rh(&self.position, &self.target, &Vector3::y());
+        if let Some(target) = self.target {
+            self.aim_at(target);
+        }
+
+        let view =
+            Isometry3::from_parts(Translation3::from(self.position.coords), self.orientation)
+                .inverse();
 
         self.view_matrix = view.to_homogeneous();
         self.vp_matrix = self.projection_matrix * self.view_matrix;
     }
 
     fn update_projection(&mut self) {
-        let projection = Perspective3::new(self.aspect_ratio, self.fov, self.near, self.far);
-        self.projection_matrix = projection.to_homogeneous();
+        self.projection_matrix = match self.projection {
+            Projection::Perspective => {
+                Perspective3::new(self.aspect_ratio, self.fov, self.near, self.far).to_homogeneous()
+            }
+            Projection::Orthographic {
+                left,
+                right,
+                bottom,
+                top,
+            } => Orthographic3::new(left, right, bottom, top, self.near, self.far).to_homogeneous(),
+        };
         self.vp_matrix = self.projection_matrix * self.view_matrix;
     }
 }
+
+// rotation from a frame with +y up to one with `up` up
+pub(crate) fn align_up(up: &Vector3<f32>) -> UnitQuaternion<f32> {
+    UnitQuaternion::rotation_between(&Vector3::y(), up)
+        .unwrap_or_else(|| UnitQuaternion::from_axis_angle(&Vector3::x_axis(), consts::PI))
+}
+
+fn roll_rotation(roll: f32) -> UnitQuaternion<f32> {
+    UnitQuaternion::from_axis_angle(&-Vector3::z_axis(), roll)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unprojected_ray_goes_through_the_projected_point() {
+        let (width, height) = (320, 240);
+        let mut camera = Camera::new(width as f32 / height as f32);
+        camera.set_position(Point3::new(3.0, 2.0, 5.0));
+        camera.set_target(Point3::new(0.0, 0.5, 0.0));
+
+        let point = Point3::new(0.7, -0.3, 0.4);
+
+        for orthographic in [false, true] {
+            if orthographic {
+                camera.set_orthographic(6.0);
+            }
+
+            let clip = camera.vp_matrix * point.to_homogeneous();
+            let ndc = clip.xyz() / clip.w;
+
+            // pixel coordinates are of the top left corner, unproject goes through the center
+            let x = (ndc.x + 1.0) * 0.5 * width as f32 - 0.5;
+            let y = (1.0 - ndc.y) * 0.5 * height as f32 - 0.5;
+
+            let ray = camera.unproject(x, y, width, height).unwrap();
+            let to_point = point - ray.origin;
+
+            assert!(to_point.dot(&ray.direction) > 0.0);
+            assert!(
+                to_point.cross(&ray.direction).norm() < 1e-4,
+                "orthographic: {orthographic}, {ray:?}"
+            );
+        }
+    }
+}
@@ -1,7 +1,4 @@
-use embedded_graphics_core::{
-    pixelcolor::Rgb565,
-    prelude::{PixelColor, Point},
-};
+use embedded_graphics_core::prelude::Point;
 
 use crate::framebuffer::RawFramebuffer;
 
@@ -11,9 +8,7 @@ pub enum DrawError {
 }
 
 pub trait GFX2DCanvas: RawFramebuffer {
-    type Color: PixelColor;
-
-    fn draw_pixel(&mut self, point: Point, color: Rgb565) -> Result<(), DrawError> {
+    fn draw_pixel(&mut self, point: Point, color: Self::Color) -> Result<(), DrawError> {
         if self.set_pixel(point, color) {
             Ok(())
         } else {
@@ -21,7 +16,7 @@ pub trait GFX2DCanvas: RawFramebuffer {
         }
     }
 
-    fn draw_line(&mut self, p1: Point, p2: Point, color: Rgb565) -> Result<(), DrawError> {
+    fn draw_line(&mut self, p1: Point, p2: Point, color: Self::Color) -> Result<(), DrawError> {
         if p1.x < 0 && p2.x < 0 {
             return Ok(());
         }
@@ -48,7 +43,7 @@ pub trait GFX2DCanvas: RawFramebuffer {
             .filter(|e| !e)
             .last();
 
-        if let Some(_) = errs {
+        if errs.is_some() {
             Err(DrawError::OutOfBounds)
         } else {
             Ok(())
@@ -59,7 +54,7 @@ pub trait GFX2DCanvas: RawFramebuffer {
         &mut self,
         p1: Point,
         p2: Point,
-        color: Rgb565,
+        color: Self::Color,
     ) -> Result<(), DrawError> {
         if p1.y < 0 || p1.y >= self.limit().y || p1.y != p2.y {
             return Err(DrawError::OutOfBounds);
@@ -68,13 +63,61 @@ pub trait GFX2DCanvas: RawFramebuffer {
         let start = p1.x.min(p2.x);
         let end = p1.x.max(p2.x);
 
-        let start = start.max(0).min(self.limit().x - 1);
-        let end = end.max(0).min(self.limit().x - 1);
-
-        for x in start..=end {
-            self.set_pixel_unchecked(Point::new(x, p1.y), color);
+        // entirely left or right of the canvas, clamping would leave a pixel at the edge
+        if end < 0 || start >= self.limit().x {
+            return Ok(());
         }
 
+        let start = start.max(0);
+        let end = end.min(self.limit().x - 1);
+
+        self.fill_span(Point::new(start, p1.y), (end - start + 1) as u32, color);
+
         Ok(())
     }
+
+    /// `width` pixels of `color` to the right of `start`, which must all be inside the canvas
+    fn fill_span(&mut self, start: Point, width: u32, color: Self::Color) {
+        for x in start.x..start.x + width as i32 {
+            self.set_pixel_unchecked(Point::new(x, start.y), color);
+        }
+    }
+
+    /// `width` pixels to the right of `start`, which must all be inside the canvas
+    fn draw_span<I: IntoIterator<Item = Self::Color>>(
+        &mut self,
+        start: Point,
+        width: u32,
+        colors: I,
+    ) {
+        for (x, color) in (start.x..start.x + width as i32).zip(colors) {
+            self.set_pixel_unchecked(Point::new(x, start.y), color);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_graphics_core::pixelcolor::BinaryColor;
+
+    use super::*;
+    use crate::framebuffer::StackFramebuffer;
+
+    #[test]
+    fn off_screen_spans_are_not_clamped_to_the_edge() {
+        let mut fb = StackFramebuffer::<8, 4, _>::new(BinaryColor::Off);
+
+        for (p1, p2) in [(-5, -1), (8, 12), (-1, -5)] {
+            fb.draw_horizontal_line(Point::new(p1, 1), Point::new(p2, 1), BinaryColor::On)
+                .unwrap();
+        }
+        assert!(fb.framebuffer.iter().flatten().all(|c| c.is_off()));
+
+        fb.draw_horizontal_line(Point::new(6, 2), Point::new(-2, 2), BinaryColor::On)
+            .unwrap();
+        assert_eq!(
+            fb.framebuffer[2].map(|c| c.is_on()),
+            [true, true, true, true, true, true, true, false]
+        );
+    }
 }
@@ -0,0 +1,233 @@
+use nalgebra::{SVector, Vector4};
+
+#[cfg(feature = "fixed")]
+use crate::fixed::Fixed;
+
+// a triangle clipped against two planes has at most five vertices
+const MAX_CLIPPED_VERTICES: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ClipPlane {
+    Near,
+    Far,
+}
+
+impl ClipPlane {
+    // signed distance in clip space, positive on the visible side
+    fn distance(self, point: &Vector4<f32>) -> f32 {
+        match self {
+            ClipPlane::Near => point.z + point.w,
+            ClipPlane::Far => point.w - point.z,
+        }
+    }
+
+    #[cfg(feature = "fixed")]
+    fn fixed_distance(self, point: &[Fixed; 4]) -> i64 {
+        let [_, _, z, w] = point.map(|c| c.0 as i64);
+
+        match self {
+            ClipPlane::Near => z + w,
+            ClipPlane::Far => w - z,
+        }
+    }
+}
+
+pub trait ClipVertex: Copy {
+    fn position(&self) -> &Vector4<f32>;
+    fn lerp(&self, other: &Self, t: f32) -> Self;
+}
+
+impl ClipVertex for Vector4<f32> {
+    fn position(&self) -> &Vector4<f32> {
+        self
+    }
+
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        self + (other - self) * t
+    }
+}
+
+// a position with attributes that are interpolated along with it
+impl<const N: usize> ClipVertex for (Vector4<f32>, SVector<f32, N>) {
+    fn position(&self) -> &Vector4<f32> {
+        &self.0
+    }
+
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        (self.0.lerp(&other.0, t), self.1.lerp(&other.1, t))
+    }
+}
+
+pub fn is_inside(point: &Vector4<f32>, planes: &[ClipPlane]) -> bool {
+    planes.iter().all(|plane| plane.distance(point) >= 0.0)
+}
+
+#[cfg(feature = "fixed")]
+pub fn is_inside_fixed(point: &[Fixed; 4], planes: &[ClipPlane]) -> bool {
+    planes.iter().all(|plane| plane.fixed_distance(point) >= 0)
+}
+
+pub fn clip_line<V: ClipVertex>(line: [V; 2], planes: &[ClipPlane]) -> Option<[V; 2]> {
+    let [mut a, mut b] = line;
+
+    for plane in planes {
+        let da = plane.distance(a.position());
+        let db = plane.distance(b.position());
+
+        if da < 0.0 && db < 0.0 {
+            return None;
+        }
+
+        if da < 0.0 {
+            a = a.lerp(&b, da / (da - db));
+        } else if db < 0.0 {
+            b = b.lerp(&a, db / (db - da));
+        }
+    }
+
+    Some([a, b])
+}
+
+/// Clips a triangle and emits the resulting triangle fan
+pub fn clip_triangle<V: ClipVertex, F: FnMut([V; 3])>(
+    triangle: [V; 3],
+    planes: &[ClipPlane],
+    mut emit: F,
+) {
+    if triangle.iter().all(|v| is_inside(v.position(), planes)) {
+        emit(triangle);
+        return;
+    }
+
+    let mut polygon = [triangle[0]; MAX_CLIPPED_VERTICES];
+    polygon[..3].copy_from_slice(&triangle);
+    let mut len = 3;
+
+    for plane in planes {
+        let input = polygon;
+        let input_len = len;
+        len = 0;
+
+        for i in 0..input_len {
+            let current = input[i];
+            let next = input[(i + 1) % input_len];
+
+            let d_current = plane.distance(current.position());
+            let d_next = plane.distance(next.position());
+
+            if d_current >= 0.0 {
+                polygon[len] = current;
+                len += 1;
+            }
+
+            if (d_current >= 0.0) != (d_next >= 0.0) {
+                polygon[len] = current.lerp(&next, d_current / (d_current - d_next));
+                len += 1;
+            }
+        }
+
+        if len < 3 {
+            return;
+        }
+    }
+
+    for i in 1..len - 1 {
+        emit([polygon[0], polygon[i], polygon[i + 1]]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector2;
+
+    use super::*;
+
+    const PLANES: &[ClipPlane] = &[ClipPlane::Near, ClipPlane::Far];
+
+    fn clipped<V: ClipVertex>(triangle: [V; 3]) -> ([[V; 3]; 4], usize) {
+        let mut triangles = [triangle; 4];
+        let mut count = 0;
+
+        clip_triangle(triangle, PLANES, |t| {
+            triangles[count] = t;
+            count += 1;
+        });
+
+        (triangles, count)
+    }
+
+    fn assert_inside(point: &Vector4<f32>) {
+        for plane in PLANES {
+            assert!(
+                plane.distance(point) > -1e-6,
+                "{point:?} outside of {plane:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn one_vertex_behind_the_near_plane_gives_two_triangles() {
+        let (triangles, count) = clipped([
+            Vector4::new(0.0, 0.0, 0.0, 1.0),
+            Vector4::new(1.0, 0.0, 0.0, 1.0),
+            Vector4::new(0.0, 1.0, -3.0, 1.0),
+        ]);
+
+        assert_eq!(count, 2);
+        triangles[..count].iter().flatten().for_each(assert_inside);
+    }
+
+    #[test]
+    fn two_vertices_behind_the_near_plane_give_one_triangle() {
+        let (triangles, count) = clipped([
+            Vector4::new(0.0, 0.0, 0.0, 1.0),
+            Vector4::new(1.0, 0.0, -3.0, 1.0),
+            Vector4::new(0.0, 1.0, -3.0, 1.0),
+        ]);
+
+        assert_eq!(count, 1);
+        triangles[0].iter().for_each(assert_inside);
+    }
+
+    #[test]
+    fn triangle_outside_of_a_plane_is_dropped() {
+        for z in [-3.0, 3.0] {
+            let (_, count) = clipped([
+                Vector4::new(0.0, 0.0, z, 1.0),
+                Vector4::new(1.0, 0.0, z, 1.0),
+                Vector4::new(0.0, 1.0, z * 2.0, 1.0),
+            ]);
+
+            assert_eq!(count, 0);
+        }
+    }
+
+    #[test]
+    fn line_is_trimmed_at_the_far_plane() {
+        let a = Vector4::new(0.0, 0.0, 0.0, 1.0);
+        let b = Vector4::new(3.0, 0.0, 2.0, 1.0);
+
+        let [p1, p2] = clip_line([a, b], PLANES).unwrap();
+
+        assert_eq!(p1, a);
+        assert!(
+            (p2 - Vector4::new(1.5, 0.0, 1.0, 1.0)).norm() < 1e-6,
+            "{p2:?}"
+        );
+        assert_eq!(clip_line([b, b * 2.0], PLANES), None);
+    }
+
+    #[test]
+    fn attributes_are_interpolated_at_the_intersection() {
+        // the attributes are x and z, so they have to match the clipped position
+        let vertex = |x: f32, z: f32| (Vector4::new(x, 1.0, z, 1.0), Vector2::new(x, z));
+
+        let (triangles, count) = clipped([vertex(0.0, 0.0), vertex(2.0, 0.0), vertex(1.0, -5.0)]);
+
+        assert_eq!(count, 2);
+        for (position, attributes) in triangles[..count].iter().flatten() {
+            assert_inside(position);
+            assert!((attributes - position.xz()).norm() < 1e-6, "{attributes:?}");
+        }
+    }
+}
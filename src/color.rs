@@ -0,0 +1,174 @@
+//! Conversion between the color of a render target and the linear rgb used for shading.
+//!
+//! Mesh, material and texture colors are `Rgb565`, lighting and gouraud interpolation are done on
+//! linear rgb with each channel in 0.0..=1.0, and the result is converted to the color of the
+//! target at the end.
+
+use embedded_graphics_core::pixelcolor::{
+    Bgr555, Bgr565, Bgr666, Bgr888, BinaryColor, Gray2, Gray4, Gray8, GrayColor, PixelColor,
+    Rgb555, Rgb565, Rgb666, Rgb888, RgbColor,
+};
+use nalgebra::Vector3;
+
+#[cfg(feature = "fixed")]
+use crate::fixed::Fixed;
+
+/// A color the engine can render to, implemented for every `embedded-graphics` color type
+pub trait ShadeColor: PixelColor + From<Rgb565> {
+    fn to_linear(self) -> Vector3<f32>;
+
+    /// Nearest color, channels outside 0.0..=1.0 are clamped
+    fn from_linear(color: Vector3<f32>) -> Self;
+
+    /// Distance between neighbouring levels of each channel in linear rgb, used for dithering
+    fn quantum() -> Vector3<f32>;
+
+    /// `to_linear` with integer operations only, the default goes through f32
+    #[cfg(feature = "fixed")]
+    fn to_linear_fixed(self) -> [Fixed; 3] {
+        let color = self.to_linear();
+
+        [color.x, color.y, color.z].map(Fixed::from_f32)
+    }
+
+    /// `from_linear` with integer operations only, the default goes through f32
+    #[cfg(feature = "fixed")]
+    fn from_linear_fixed(color: [Fixed; 3]) -> Self {
+        Self::from_linear(Vector3::from(color.map(Fixed::to_f32)))
+    }
+}
+
+macro_rules! impl_rgb {
+    ($($type:ident),+) => {
+        $(impl ShadeColor for $type {
+            fn to_linear(self) -> Vector3<f32> {
+                Vector3::new(
+                    self.r() as f32 / Self::MAX_R as f32,
+                    self.g() as f32 / Self::MAX_G as f32,
+                    self.b() as f32 / Self::MAX_B as f32,
+                )
+            }
+
+            fn from_linear(color: Vector3<f32>) -> Self {
+                Self::new(
+                    channel_from_linear(color.x, Self::MAX_R),
+                    channel_from_linear(color.y, Self::MAX_G),
+                    channel_from_linear(color.z, Self::MAX_B),
+                )
+            }
+
+            fn quantum() -> Vector3<f32> {
+                Vector3::new(
+                    1.0 / Self::MAX_R as f32,
+                    1.0 / Self::MAX_G as f32,
+                    1.0 / Self::MAX_B as f32,
+                )
+            }
+
+            #[cfg(feature = "fixed")]
+            fn to_linear_fixed(self) -> [Fixed; 3] {
+                [
+                    channel_to_fixed(self.r(), Self::MAX_R),
+                    channel_to_fixed(self.g(), Self::MAX_G),
+                    channel_to_fixed(self.b(), Self::MAX_B),
+                ]
+            }
+
+            #[cfg(feature = "fixed")]
+            fn from_linear_fixed(color: [Fixed; 3]) -> Self {
+                Self::new(
+                    channel_from_fixed(color[0], Self::MAX_R),
+                    channel_from_fixed(color[1], Self::MAX_G),
+                    channel_from_fixed(color[2], Self::MAX_B),
+                )
+            }
+        })+
+    };
+}
+
+impl_rgb!(Rgb555, Bgr555, Rgb565, Bgr565, Rgb666, Bgr666, Rgb888, Bgr888);
+
+macro_rules! impl_gray {
+    ($($type:ident),+) => {
+        $(impl ShadeColor for $type {
+            fn to_linear(self) -> Vector3<f32> {
+                Vector3::repeat(self.luma() as f32 / Self::WHITE.luma() as f32)
+            }
+
+            fn from_linear(color: Vector3<f32>) -> Self {
+                Self::new(channel_from_linear(luma(&color), Self::WHITE.luma()))
+            }
+
+            fn quantum() -> Vector3<f32> {
+                Vector3::repeat(1.0 / Self::WHITE.luma() as f32)
+            }
+
+            #[cfg(feature = "fixed")]
+            fn to_linear_fixed(self) -> [Fixed; 3] {
+                [channel_to_fixed(self.luma(), Self::WHITE.luma()); 3]
+            }
+
+            #[cfg(feature = "fixed")]
+            fn from_linear_fixed(color: [Fixed; 3]) -> Self {
+                Self::new(channel_from_fixed(luma_fixed(&color), Self::WHITE.luma()))
+            }
+        })+
+    };
+}
+
+impl_gray!(Gray2, Gray4, Gray8);
+
+impl ShadeColor for BinaryColor {
+    fn to_linear(self) -> Vector3<f32> {
+        Vector3::repeat(if self.is_on() { 1.0 } else { 0.0 })
+    }
+
+    fn from_linear(color: Vector3<f32>) -> Self {
+        (luma(&color) >= 0.5).into()
+    }
+
+    fn quantum() -> Vector3<f32> {
+        Vector3::repeat(1.0)
+    }
+
+    #[cfg(feature = "fixed")]
+    fn to_linear_fixed(self) -> [Fixed; 3] {
+        [if self.is_on() {
+            Fixed::ONE
+        } else {
+            Fixed::ZERO
+        }; 3]
+    }
+
+    #[cfg(feature = "fixed")]
+    fn from_linear_fixed(color: [Fixed; 3]) -> Self {
+        (luma_fixed(&color) >= Fixed(Fixed::ONE.0 / 2)).into()
+    }
+}
+
+// the same weights embedded-graphics uses to convert rgb to gray
+fn luma(color: &Vector3<f32>) -> f32 {
+    color.dot(&Vector3::new(0.299, 0.587, 0.114))
+}
+
+fn channel_from_linear(value: f32, max: u8) -> u8 {
+    (value.clamp(0.0, 1.0) * max as f32 + 0.5) as u8
+}
+
+#[cfg(feature = "fixed")]
+fn luma_fixed(color: &[Fixed; 3]) -> Fixed {
+    // 0.299, 0.587 and 0.114 with 16 fractional bits, adding up to one
+    crate::fixed::dot(color, &[Fixed(19595), Fixed(38470), Fixed(7471)])
+}
+
+#[cfg(feature = "fixed")]
+fn channel_to_fixed(value: u8, max: u8) -> Fixed {
+    Fixed(((value as i32) << Fixed::FRAC_BITS) / max as i32)
+}
+
+#[cfg(feature = "fixed")]
+fn channel_from_fixed(value: Fixed, max: u8) -> u8 {
+    let value = value.clamp(Fixed::ZERO, Fixed::ONE);
+
+    ((value.0 * max as i32 + (1 << (Fixed::FRAC_BITS - 1))) >> Fixed::FRAC_BITS) as u8
+}
@@ -0,0 +1,50 @@
+use embedded_graphics_core::prelude::Point;
+
+/// A 16-bit depth buffer backed by caller-supplied memory.
+///
+/// The buffer must hold one entry per framebuffer pixel, in row-major order.
+/// Smaller values are closer to the camera.
+pub struct DepthBuffer<'a> {
+    buffer: &'a mut [u16],
+    width: usize,
+}
+
+impl<'a> DepthBuffer<'a> {
+    pub fn new(buffer: &'a mut [u16], width: usize) -> DepthBuffer<'a> {
+        assert!(width > 0, "Depth buffer width must not be zero");
+        assert!(
+            buffer.chunks_exact(width).remainder().is_empty(),
+            "Depth buffer length is not a multiple of its width"
+        );
+
+        DepthBuffer { buffer, width }
+    }
+
+    /// Resets every entry to the far plane, call this at the start of each frame
+    pub fn clear(&mut self) {
+        self.buffer.fill(u16::MAX);
+    }
+
+    pub fn limit(&self) -> Point {
+        Point::new(self.width as i32, (self.buffer.len() / self.width) as i32)
+    }
+
+    /// Stores `depth` at `point` if it is closer than the current value
+    #[inline]
+    pub fn test_and_set(&mut self, point: Point, depth: u16) -> bool {
+        if point.x < 0 || point.y < 0 || point.x as usize >= self.width {
+            return false;
+        }
+
+        match self
+            .buffer
+            .get_mut(point.y as usize * self.width + point.x as usize)
+        {
+            Some(stored) if depth < *stored => {
+                *stored = depth;
+                true
+            }
+            _ => false,
+        }
+    }
+}
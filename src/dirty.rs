@@ -0,0 +1,236 @@
+use embedded_graphics_core::geometry::{Point, Size};
+use embedded_graphics_core::primitives::Rectangle;
+
+use crate::canvas::GFX2DCanvas;
+use crate::framebuffer::RawFramebuffer;
+
+/// Inclusive bounding box of the pixels drawn during a frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Bounds {
+    min: Point,
+    max: Point,
+}
+
+impl Bounds {
+    fn new(min: Point, max: Point) -> Bounds {
+        Bounds { min, max }
+    }
+
+    fn union(self, other: Bounds) -> Bounds {
+        Bounds::new(
+            self.min.component_min(other.min),
+            self.max.component_max(other.max),
+        )
+    }
+
+    fn intersects(&self, other: &Bounds) -> bool {
+        self.min.x <= other.max.x
+            && other.min.x <= self.max.x
+            && self.min.y <= other.max.y
+            && other.min.y <= self.max.y
+    }
+
+    fn area(&self) -> u64 {
+        (self.max.x - self.min.x + 1) as u64 * (self.max.y - self.min.y + 1) as u64
+    }
+
+    fn to_rectangle(self) -> Rectangle {
+        Rectangle::with_corners(self.min, self.max)
+    }
+}
+
+/// Tracks the area drawn to a canvas, so that only the parts of the display that changed have
+/// to be sent to the panel.
+///
+/// A frame goes like this:
+/// - `clear` the footprint of the previous frame to the background
+/// - render to the tracker like to any other canvas
+/// - send every rectangle from `dirty_regions` to the display
+/// - `end_frame`
+///
+/// The first frame has no previous footprint, so the whole canvas has to be cleared and sent once.
+pub struct DirtyTracker<C: GFX2DCanvas> {
+    canvas: C,
+    current: Option<Bounds>,
+    previous: Option<Bounds>,
+}
+
+impl<C: GFX2DCanvas> DirtyTracker<C> {
+    pub fn new(canvas: C) -> DirtyTracker<C> {
+        DirtyTracker {
+            canvas,
+            current: None,
+            previous: None,
+        }
+    }
+
+    pub fn canvas(&self) -> &C {
+        &self.canvas
+    }
+
+    /// Pixels drawn to the canvas directly are not tracked
+    pub fn canvas_mut(&mut self) -> &mut C {
+        &mut self.canvas
+    }
+
+    pub fn into_inner(self) -> C {
+        self.canvas
+    }
+
+    /// Bounding rectangle of the pixels drawn since the last `end_frame`
+    pub fn current(&self) -> Option<Rectangle> {
+        self.current.map(Bounds::to_rectangle)
+    }
+
+    /// Bounding rectangle of the pixels drawn during the previous frame
+    pub fn previous(&self) -> Option<Rectangle> {
+        self.previous.map(Bounds::to_rectangle)
+    }
+
+    /// Marks an area as changed, for drawing done outside of the tracker
+    pub fn mark_dirty(&mut self, area: &Rectangle) {
+        let area = area.intersection(&Rectangle::new(Point::zero(), self.canvas_size()));
+
+        if let Some(bottom_right) = area.bottom_right() {
+            self.expand(area.top_left, bottom_right);
+        }
+    }
+
+    /// Fills the footprint of the previous frame, without marking it as drawn in this one
+    pub fn clear(&mut self, color: C::Color) {
+        let Some(bounds) = self.previous else {
+            return;
+        };
+
+        for y in bounds.min.y..=bounds.max.y {
+            self.canvas
+                .draw_horizontal_line(
+                    Point::new(bounds.min.x, y),
+                    Point::new(bounds.max.x, y),
+                    color,
+                )
+                .ok();
+        }
+    }
+
+    /// The areas that differ from what the display shows, covering this frame and the pixels of
+    /// the previous one that have to be erased.
+    ///
+    /// The two footprints are merged into one rectangle when they overlap or when their bounding
+    /// rectangle is no larger than both of them, so that no pixel is sent twice.
+    pub fn dirty_regions(&self) -> impl Iterator<Item = Rectangle> {
+        let regions = match (self.current, self.previous) {
+            (Some(current), Some(previous))
+                if current.intersects(&previous)
+                    || current.union(previous).area() <= current.area() + previous.area() =>
+            {
+                [Some(current.union(previous)), None]
+            }
+            (current, previous) => [current, previous],
+        };
+
+        regions.into_iter().flatten().map(Bounds::to_rectangle)
+    }
+
+    /// Call once the dirty regions have been sent, this frame becomes the previous one
+    pub fn end_frame(&mut self) {
+        self.previous = self.current.take();
+    }
+
+    fn canvas_size(&self) -> Size {
+        let limit = self.canvas.limit();
+
+        Size::new(limit.x.max(0) as u32, limit.y.max(0) as u32)
+    }
+
+    #[inline]
+    fn expand(&mut self, min: Point, max: Point) {
+        let bounds = Bounds::new(min, max);
+
+        self.current = Some(match self.current {
+            Some(current) => current.union(bounds),
+            None => bounds,
+        });
+    }
+}
+
+impl<C: GFX2DCanvas> GFX2DCanvas for DirtyTracker<C> {
+    fn fill_span(&mut self, start: Point, width: u32, color: C::Color) {
+        if width == 0 {
+            return;
+        }
+
+        self.canvas.fill_span(start, width, color);
+        self.expand(start, start + Point::new(width as i32 - 1, 0));
+    }
+
+    fn draw_span<I: IntoIterator<Item = C::Color>>(&mut self, start: Point, width: u32, colors: I) {
+        if width == 0 {
+            return;
+        }
+
+        self.canvas.draw_span(start, width, colors);
+        self.expand(start, start + Point::new(width as i32 - 1, 0));
+    }
+}
+
+impl<C: GFX2DCanvas> RawFramebuffer for DirtyTracker<C> {
+    type Color = C::Color;
+
+    fn set_pixel(&mut self, point: Point, color: C::Color) -> bool {
+        let inside = self.canvas.set_pixel(point, color);
+
+        if inside {
+            self.expand(point, point);
+        }
+
+        inside
+    }
+
+    fn set_pixel_unchecked(&mut self, point: Point, color: C::Color) {
+        self.canvas.set_pixel_unchecked(point, color);
+        self.expand(point, point);
+    }
+
+    fn limit(&self) -> Point {
+        self.canvas.limit()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_graphics_core::pixelcolor::BinaryColor;
+
+    use super::*;
+    use crate::framebuffer::StackFramebuffer;
+
+    #[test]
+    fn off_screen_spans_are_not_dirty() {
+        let mut tracker = DirtyTracker::new(StackFramebuffer::<8, 4, _>::new(BinaryColor::Off));
+
+        for (p1, p2) in [(-5, -1), (8, 12)] {
+            tracker
+                .draw_horizontal_line(Point::new(p1, 1), Point::new(p2, 1), BinaryColor::On)
+                .unwrap();
+        }
+        assert!(tracker
+            .draw_horizontal_line(Point::new(0, 4), Point::new(7, 4), BinaryColor::On)
+            .is_err());
+
+        assert_eq!(tracker.current(), None);
+        assert!(tracker
+            .canvas()
+            .framebuffer
+            .iter()
+            .flatten()
+            .all(|c| c.is_off()));
+
+        tracker
+            .draw_horizontal_line(Point::new(6, 2), Point::new(10, 2), BinaryColor::On)
+            .unwrap();
+        assert_eq!(
+            tracker.current(),
+            Some(Rectangle::new(Point::new(6, 2), Size::new(2, 1)))
+        );
+    }
+}
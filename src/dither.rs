@@ -0,0 +1,89 @@
+//! Dithering, for targets with fewer color levels than the shading produces.
+//!
+//! Render to a wide color such as `Rgb888` or `Gray8`, then either draw every primitive with
+//! `draw::draw_dithered`, which quantizes each pixel with an ordered pattern, or draw to a full
+//! framebuffer of the wide color and convert it to the display with `floyd_steinberg`.
+
+use embedded_graphics_core::prelude::Point;
+use nalgebra::Vector3;
+
+use crate::canvas::GFX2DCanvas;
+use crate::color::ShadeColor;
+
+/// Ordered dithering with a Bayer matrix, larger matrices give more apparent levels
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Dither {
+    Bayer2,
+    #[default]
+    Bayer4,
+    Bayer8,
+}
+
+// the top left 2x2 and 4x4 corners, shifted right, are the smaller matrices
+const BAYER_8: [[u8; 8]; 8] = [
+    [0, 32, 8, 40, 2, 34, 10, 42],
+    [48, 16, 56, 24, 50, 18, 58, 26],
+    [12, 44, 4, 36, 14, 46, 6, 38],
+    [60, 28, 52, 20, 62, 30, 54, 22],
+    [3, 35, 11, 43, 1, 33, 9, 41],
+    [51, 19, 59, 27, 49, 17, 57, 25],
+    [15, 47, 7, 39, 13, 45, 5, 37],
+    [63, 31, 55, 23, 61, 29, 53, 21],
+];
+
+impl Dither {
+    /// Offset added to the pixel before quantizing, in levels of the target, within -0.5..0.5
+    pub fn threshold(self, x: i32, y: i32) -> f32 {
+        let (size, shift) = match self {
+            Dither::Bayer2 => (2, 4),
+            Dither::Bayer4 => (4, 2),
+            Dither::Bayer8 => (8, 0),
+        };
+
+        let value = BAYER_8[(y & (size - 1)) as usize][(x & (size - 1)) as usize] >> shift;
+
+        (value as f32 + 0.5) / (size * size) as f32 - 0.5
+    }
+
+    /// The color of the target for a pixel with the linear rgb `color`
+    #[inline]
+    pub fn apply<C: ShadeColor>(self, color: Vector3<f32>, x: i32, y: i32) -> C {
+        C::from_linear(color + C::quantum() * self.threshold(x, y))
+    }
+}
+
+/// Error diffusion of a whole frame, row `y` of `source` is drawn to row `y` of `target`.
+///
+/// Uses two rows of `W` linear colors on the stack, pixels outside of the target are skipped.
+pub fn floyd_steinberg<const W: usize, S: ShadeColor, D: GFX2DCanvas>(
+    source: &[[S; W]],
+    target: &mut D,
+) where
+    D::Color: ShadeColor,
+{
+    let mut error = [Vector3::<f32>::zeros(); W];
+    let mut next_error = [Vector3::<f32>::zeros(); W];
+
+    for (y, row) in source.iter().enumerate() {
+        for (x, pixel) in row.iter().enumerate() {
+            // clamped so that the error of colors the target can't show doesn't pile up
+            let color = (pixel.to_linear() + error[x]).map(|c| c.clamp(0.0, 1.0));
+            let quantized = D::Color::from_linear(color);
+            let residual = color - quantized.to_linear();
+
+            if x + 1 < W {
+                error[x + 1] += residual * (7.0 / 16.0);
+                next_error[x + 1] += residual * (1.0 / 16.0);
+            }
+            if x > 0 {
+                next_error[x - 1] += residual * (3.0 / 16.0);
+            }
+            next_error[x] += residual * (5.0 / 16.0);
+
+            target.set_pixel(Point::new(x as i32, y as i32), quantized);
+        }
+
+        error = next_error;
+        next_error = [Vector3::zeros(); W];
+    }
+}
@@ -1,6 +1,15 @@
+use core::marker::PhantomData;
+use core::ops::RangeInclusive;
+
 use embedded_graphics_core::prelude::Point;
 
+use nalgebra::Point3;
+
 use crate::canvas::{DrawError, GFX2DCanvas};
+use crate::color::ShadeColor;
+use crate::depth::DepthBuffer;
+use crate::dither::Dither;
+use crate::texture::Texture;
 use crate::DrawPrimitive;
 
 const FP_SHIFT: i64 = 16;
@@ -12,7 +21,7 @@ struct EdgeStepper {
 }
 
 impl EdgeStepper {
-    fn new(start: Point, end: Point, y: i32) -> Self {
+    fn new(start: Point3<i32>, end: Point3<i32>, y: i32) -> Self {
         let dy = (end.y - start.y) as i64;
         debug_assert!(dy > 0);
 
@@ -40,74 +49,564 @@ fn fixed_to_i32(value: i64) -> i32 {
     }
 }
 
+// Linear interpolation of a per-vertex value over the plane of a triangle
+#[derive(Clone, Copy)]
+struct Gradient {
+    origin: Point,
+    value: f32,
+    dx: f32,
+    dy: f32,
+}
+
+impl Gradient {
+    // the vertices can be in any order, as long as the values match them
+    fn new(vertices: &[Point3<i32>; 3], values: [f32; 3]) -> Self {
+        let [p1, p2, p3] = vertices;
+        let area2 = area2(vertices) as f32;
+
+        let e1 = ((p2.x - p1.x) as f32, (p2.y - p1.y) as f32);
+        let e2 = ((p3.x - p1.x) as f32, (p3.y - p1.y) as f32);
+        let d1 = values[1] - values[0];
+        let d2 = values[2] - values[0];
+
+        Self {
+            origin: Point::new(p1.x, p1.y),
+            value: values[0],
+            dx: (d1 * e2.1 - d2 * e1.1) / area2,
+            dy: (d2 * e1.0 - d1 * e2.0) / area2,
+        }
+    }
+
+    #[inline]
+    fn at(&self, x: i32, y: i32) -> f32 {
+        self.value + self.dx * (x - self.origin.x) as f32 + self.dy * (y - self.origin.y) as f32
+    }
+}
+
+// Depth over the plane of a triangle, in the same fixed point as the edges
+#[derive(Clone, Copy)]
+struct DepthGradient {
+    origin: Point,
+    z: i64,
+    dx: i64,
+    dy: i64,
+}
+
+impl DepthGradient {
+    // set up in i128, the products of far off-screen coordinates and depths don't fit in i64
+    fn new(vertices: &[Point3<i32>; 3], area2: i64) -> Self {
+        let [p1, p2, p3] = vertices;
+
+        let e1 = ((p2.x - p1.x) as i128, (p2.y - p1.y) as i128);
+        let e2 = ((p3.x - p1.x) as i128, (p3.y - p1.y) as i128);
+        let d1 = p2.z as i128 - p1.z as i128;
+        let d2 = p3.z as i128 - p1.z as i128;
+
+        let slope = |n: i128| {
+            ((n << FP_SHIFT) / area2 as i128).clamp(i64::MIN as i128, i64::MAX as i128) as i64
+        };
+
+        Self {
+            origin: Point::new(p1.x, p1.y),
+            z: (p1.z as i64) << FP_SHIFT,
+            dx: slope(d1 * e2.1 - d2 * e1.1),
+            dy: slope(d2 * e1.0 - d1 * e2.0),
+        }
+    }
+
+    #[inline]
+    fn at(&self, x: i32, y: i32) -> i64 {
+        self.z
+            .saturating_add(self.dx.saturating_mul((x - self.origin.x) as i64))
+            .saturating_add(self.dy.saturating_mul((y - self.origin.y) as i64))
+    }
+}
+
+// u16::MAX is left for cleared entries, so that geometry beyond the far plane, which is drawn
+// when far clipping is off, still passes the depth test
+#[inline]
+fn to_depth(z: i64) -> u16 {
+    z.clamp(0, (u16::MAX - 1) as i64) as u16
+}
+
+// per-pixel color source for the triangle rasterizer
+trait Shader<C> {
+    // moves to the first pixel of a span
+    fn start_span(&mut self, x: i32, y: i32);
+
+    // returns the color of the current pixel and steps to the next one
+    fn next_pixel(&mut self) -> C;
+}
+
+struct FlatShader<C>(C);
+
+impl<C: Copy> Shader<C> for FlatShader<C> {
+    fn start_span(&mut self, _x: i32, _y: i32) {}
+
+    #[inline]
+    fn next_pixel(&mut self) -> C {
+        self.0
+    }
+}
+
+// per-vertex values stepped across a span, optionally with perspective correction
+struct Varyings<const N: usize> {
+    gradients: [Gradient; N],
+    inv_w: Option<Gradient>,
+    current: [f32; N],
+    current_inv_w: f32,
+}
+
+impl<const N: usize> Varyings<N> {
+    fn new(vertices: &[Point3<i32>; 3], values: [[f32; N]; 3], inv_w: Option<[f32; 3]>) -> Self {
+        // perspective correct interpolation steps value / w and 1 / w, which are affine in screen space
+        let values = match inv_w {
+            Some(inv_w) => [0, 1, 2].map(|i| values[i].map(|v| v * inv_w[i])),
+            None => values,
+        };
+
+        Self {
+            gradients: core::array::from_fn(|k| {
+                Gradient::new(vertices, [values[0][k], values[1][k], values[2][k]])
+            }),
+            inv_w: inv_w.map(|inv_w| Gradient::new(vertices, inv_w)),
+            current: [0.0; N],
+            current_inv_w: 1.0,
+        }
+    }
+
+    fn start_span(&mut self, x: i32, y: i32) {
+        for (current, gradient) in self.current.iter_mut().zip(&self.gradients) {
+            *current = gradient.at(x, y);
+        }
+
+        if let Some(inv_w) = &self.inv_w {
+            self.current_inv_w = inv_w.at(x, y);
+        }
+    }
+
+    // returns the values at the current pixel and steps to the next one
+    #[inline]
+    fn next(&mut self) -> [f32; N] {
+        let values = match &self.inv_w {
+            Some(inv_w) => {
+                let w = 1.0 / self.current_inv_w;
+                self.current_inv_w += inv_w.dx;
+
+                self.current.map(|v| v * w)
+            }
+            None => self.current,
+        };
+
+        for (current, gradient) in self.current.iter_mut().zip(&self.gradients) {
+            *current += gradient.dx;
+        }
+
+        values
+    }
+}
+
+// colors are interpolated in linear rgb and converted per pixel
+struct GouraudShader(Varyings<3>);
+
+impl GouraudShader {
+    fn new<C: ShadeColor>(
+        vertices: &[Point3<i32>; 3],
+        colors: [C; 3],
+        inv_w: Option<[f32; 3]>,
+    ) -> Self {
+        let colors = colors.map(|c| c.to_linear().into());
+
+        Self(Varyings::new(vertices, colors, inv_w))
+    }
+}
+
+impl<C: ShadeColor> Shader<C> for GouraudShader {
+    fn start_span(&mut self, x: i32, y: i32) {
+        self.0.start_span(x, y);
+    }
+
+    #[inline]
+    fn next_pixel(&mut self) -> C {
+        C::from_linear(self.0.next().into())
+    }
+}
+
+struct TextureShader<'a> {
+    texture: Texture<'a>,
+    uv: Varyings<2>,
+}
+
+impl<'a> TextureShader<'a> {
+    fn new(
+        vertices: &[Point3<i32>; 3],
+        uvs: [[f32; 2]; 3],
+        texture: Texture<'a>,
+        inv_w: Option<[f32; 3]>,
+    ) -> Self {
+        let size = [texture.width() as f32, texture.height() as f32];
+        let uvs = uvs.map(|uv| [uv[0] * size[0], uv[1] * size[1]]);
+
+        Self {
+            texture,
+            uv: Varyings::new(vertices, uvs, inv_w),
+        }
+    }
+}
+
+impl<C: ShadeColor> Shader<C> for TextureShader<'_> {
+    fn start_span(&mut self, x: i32, y: i32) {
+        self.uv.start_span(x, y);
+    }
+
+    #[inline]
+    fn next_pixel(&mut self) -> C {
+        let [u, v] = self.uv.next();
+
+        self.texture.sample(u, v).into()
+    }
+}
+
+// the colors of another shader, quantized with an ordered dither
+struct DitherShader<S, C> {
+    shader: S,
+    dither: Dither,
+    x: i32,
+    y: i32,
+    source: PhantomData<C>,
+}
+
+impl<S, C> DitherShader<S, C> {
+    fn new(shader: S, dither: Dither) -> Self {
+        Self {
+            shader,
+            dither,
+            x: 0,
+            y: 0,
+            source: PhantomData,
+        }
+    }
+}
+
+impl<S: Shader<C>, C: ShadeColor, T: ShadeColor> Shader<T> for DitherShader<S, C> {
+    fn start_span(&mut self, x: i32, y: i32) {
+        self.shader.start_span(x, y);
+        self.x = x;
+        self.y = y;
+    }
+
+    #[inline]
+    fn next_pixel(&mut self) -> T {
+        let color = self.shader.next_pixel().to_linear();
+        self.x += 1;
+
+        self.dither.apply(color, self.x - 1, self.y)
+    }
+}
+
+#[inline]
+pub fn draw<C: ShadeColor, D: GFX2DCanvas<Color = C>>(
+    primitive: &DrawPrimitive<C>,
+    fb: &mut D,
+) -> Result<(), DrawError> {
+    draw_primitive(primitive, fb, None)
+}
+
+#[inline]
+pub fn draw_with_depth<C: ShadeColor, D: GFX2DCanvas<Color = C>>(
+    primitive: &DrawPrimitive<C>,
+    fb: &mut D,
+    depth: &mut DepthBuffer,
+) -> Result<(), DrawError> {
+    debug_assert_eq!(depth.limit(), fb.limit());
+
+    draw_primitive(primitive, fb, Some(depth))
+}
+
+/// Draws a primitive of a wider color than the canvas, such as `Rgb888` on an `Rgb565` or
+/// `BinaryColor` display, quantizing every pixel with an ordered dither
+#[inline]
+pub fn draw_dithered<C: ShadeColor, D: GFX2DCanvas>(
+    primitive: &DrawPrimitive<C>,
+    fb: &mut D,
+    dither: Dither,
+) -> Result<(), DrawError>
+where
+    D::Color: ShadeColor,
+{
+    draw_primitive_dithered(primitive, fb, None, dither)
+}
+
 #[inline]
-pub fn draw<D: GFX2DCanvas<Color = embedded_graphics_core::pixelcolor::Rgb565>>(
-    primitive: &DrawPrimitive,
+pub fn draw_dithered_with_depth<C: ShadeColor, D: GFX2DCanvas>(
+    primitive: &DrawPrimitive<C>,
     fb: &mut D,
+    depth: &mut DepthBuffer,
+    dither: Dither,
+) -> Result<(), DrawError>
+where
+    D::Color: ShadeColor,
+{
+    debug_assert_eq!(depth.limit(), fb.limit());
+
+    draw_primitive_dithered(primitive, fb, Some(depth), dither)
+}
+
+fn draw_primitive<C: ShadeColor, D: GFX2DCanvas<Color = C>>(
+    primitive: &DrawPrimitive<C>,
+    fb: &mut D,
+    depth: Option<&mut DepthBuffer>,
 ) -> Result<(), DrawError> {
     match *primitive {
-        DrawPrimitive::Line([p1, p2], color) => {
-            fb.draw_line(Point::new(p1.x, p1.y), Point::new(p2.x, p2.y), color)?;
-        }
+        DrawPrimitive::Line([p1, p2], color) => match depth {
+            Some(depth) => draw_line_shaded(p1, p2, fb, Some(depth), |_| color),
+            None => fb.draw_line(Point::new(p1.x, p1.y), Point::new(p2.x, p2.y), color)?,
+        },
         DrawPrimitive::ColoredPoint(p, c) => {
-            let p = embedded_graphics_core::geometry::Point::new(p.x, p.y);
+            let point = Point::new(p.x, p.y);
+
+            if let Some(depth) = depth {
+                if !fb.is_in_bounds(&point) || !depth.test_and_set(point, to_depth(p.z as i64)) {
+                    return Ok(());
+                }
+            }
 
-            fb.draw_pixel(p, c)?;
+            fb.draw_pixel(point, c)?;
+        }
+        DrawPrimitive::ColoredTriangle(vertices, color) => {
+            fill_triangle(vertices, color, fb, depth);
         }
-        DrawPrimitive::ColoredTriangle(mut vertices, color) => {
-            //sort vertices by y
-            vertices.sort_by(|a, b| a.y.cmp(&b.y));
+        DrawPrimitive::GouraudTriangle(vertices, colors, inv_w) => {
+            let shader = GouraudShader::new(&vertices, colors, inv_w);
+            shade_triangle(vertices, shader, fb, depth);
+        }
+        DrawPrimitive::TexturedTriangle(vertices, uvs, texture, inv_w) => {
+            let shader = TextureShader::new(&vertices, uvs, texture, inv_w);
+            shade_triangle(vertices, shader, fb, depth);
+        }
+    }
 
-            let p1 = Point::new(vertices[0].x, vertices[0].y);
-            let p2 = Point::new(vertices[1].x, vertices[1].y);
-            let p3 = Point::new(vertices[2].x, vertices[2].y);
+    Ok(())
+}
 
-            if p1.x < 0 && p2.x < 0 && p3.x < 0 {
-                return Ok(());
-            }
-            if p1.x >= fb.limit().x as i32
-                && p2.x >= fb.limit().x as i32
-                && p3.x >= fb.limit().x as i32
-            {
-                return Ok(());
-            }
-            if p1.y < 0 && p2.y < 0 && p3.y < 0 {
-                return Ok(());
-            }
+fn draw_primitive_dithered<C: ShadeColor, D: GFX2DCanvas>(
+    primitive: &DrawPrimitive<C>,
+    fb: &mut D,
+    depth: Option<&mut DepthBuffer>,
+    dither: Dither,
+) -> Result<(), DrawError>
+where
+    D::Color: ShadeColor,
+{
+    match *primitive {
+        DrawPrimitive::Line([p1, p2], color) => {
+            let color = color.to_linear();
+            draw_line_shaded(p1, p2, fb, depth, |p| dither.apply(color, p.x, p.y));
+        }
+        DrawPrimitive::ColoredPoint(p, c) => {
+            let point = Point::new(p.x, p.y);
 
-            if p1.y >= fb.limit().y as i32
-                && p2.y >= fb.limit().y as i32
-                && p3.y >= fb.limit().y as i32
-            {
-                return Ok(());
+            if let Some(depth) = depth {
+                if !fb.is_in_bounds(&point) || !depth.test_and_set(point, to_depth(p.z as i64)) {
+                    return Ok(());
+                }
             }
 
-            fill_triangle(p1, p2, p3, color, fb)?;
+            fb.draw_pixel(point, dither.apply(c.to_linear(), p.x, p.y))?;
+        }
+        DrawPrimitive::ColoredTriangle(vertices, color) => {
+            let shader = DitherShader::new(FlatShader(color), dither);
+            shade_triangle(vertices, shader, fb, depth);
+        }
+        DrawPrimitive::GouraudTriangle(vertices, colors, inv_w) => {
+            let shader = GouraudShader::new(&vertices, colors, inv_w);
+            let shader = DitherShader::<_, C>::new(shader, dither);
+            shade_triangle(vertices, shader, fb, depth);
+        }
+        DrawPrimitive::TexturedTriangle(vertices, uvs, texture, inv_w) => {
+            let shader = TextureShader::new(&vertices, uvs, texture, inv_w);
+            let shader = DitherShader::<_, C>::new(shader, dither);
+            shade_triangle(vertices, shader, fb, depth);
         }
     }
 
     Ok(())
 }
 
-fn fill_triangle<D: GFX2DCanvas<Color = embedded_graphics_core::pixelcolor::Rgb565>>(
-    p1: Point,
-    p2: Point,
-    p3: Point,
-    color: embedded_graphics_core::pixelcolor::Rgb565,
-    fb: &mut D,
-) -> Result<(), DrawError> {
-    let area2 =
-        (p2.x - p1.x) as i64 * (p3.y - p1.y) as i64 - (p2.y - p1.y) as i64 * (p3.x - p1.x) as i64;
+// sorts the vertices by y, returns None if there is nothing to rasterize
+fn setup_triangle(mut vertices: [Point3<i32>; 3], limit: Point) -> Option<([Point3<i32>; 3], i64)> {
+    // stable, without the allocation of slice::sort
+    if vertices[1].y < vertices[0].y {
+        vertices.swap(0, 1);
+    }
+    if vertices[2].y < vertices[1].y {
+        vertices.swap(1, 2);
+        if vertices[1].y < vertices[0].y {
+            vertices.swap(0, 1);
+        }
+    }
+
+    if is_off_screen(&vertices, limit) {
+        return None;
+    }
+
+    let area2 = area2(&vertices);
 
     if area2 == 0 {
-        return Ok(());
+        return None;
+    }
+
+    Some((vertices, area2))
+}
+
+fn is_off_screen(vertices: &[Point3<i32>; 3], limit: Point) -> bool {
+    vertices.iter().all(|p| p.x < 0)
+        || vertices.iter().all(|p| p.x >= limit.x)
+        || vertices.iter().all(|p| p.y < 0)
+        || vertices.iter().all(|p| p.y >= limit.y)
+}
+
+fn area2(vertices: &[Point3<i32>; 3]) -> i64 {
+    let [p1, p2, p3] = vertices;
+
+    (p2.x - p1.x) as i64 * (p3.y - p1.y) as i64 - (p2.y - p1.y) as i64 * (p3.x - p1.x) as i64
+}
+
+// per-pixel line, pixels outside of the canvas are skipped
+fn draw_line_shaded<D: GFX2DCanvas, F: FnMut(Point) -> D::Color>(
+    p1: Point3<i32>,
+    p2: Point3<i32>,
+    fb: &mut D,
+    mut depth: Option<&mut DepthBuffer>,
+    mut color: F,
+) {
+    let steps = (p2.x - p1.x).abs().max((p2.y - p1.y).abs()).max(1) as i64;
+    let z_step = ((p2.z as i64 - p1.z as i64) << FP_SHIFT) / steps;
+    let mut z = (p1.z as i64) << FP_SHIFT;
+
+    for (x, y) in line_drawing::Bresenham::new((p1.x, p1.y), (p2.x, p2.y)) {
+        let point = Point::new(x, y);
+        let current_z = z;
+        z += z_step;
+
+        if !fb.is_in_bounds(&point) {
+            continue;
+        }
+
+        let visible = match depth.as_mut() {
+            Some(depth) => depth.test_and_set(point, to_depth(current_z >> FP_SHIFT)),
+            None => true,
+        };
+
+        if visible {
+            fb.set_pixel_unchecked(point, color(point));
+        }
+    }
+}
+
+fn fill_triangle<C: ShadeColor, D: GFX2DCanvas<Color = C>>(
+    vertices: [Point3<i32>; 3],
+    color: C,
+    fb: &mut D,
+    depth: Option<&mut DepthBuffer>,
+) {
+    match depth {
+        Some(depth) => shade_triangle(vertices, FlatShader(color), fb, Some(depth)),
+        None => {
+            let Some((vertices, area2)) = setup_triangle(vertices, fb.limit()) else {
+                return;
+            };
+
+            scan_triangle(vertices, area2, fb.limit().y, |y, left_x, right_x| {
+                fb.draw_horizontal_line(Point::new(left_x, y), Point::new(right_x, y), color)
+                    .ok();
+            });
+        }
     }
+}
+
+// per-pixel fill, the shader must have been set up with the same vertices
+fn shade_triangle<C, D: GFX2DCanvas<Color = C>, S: Shader<C>>(
+    vertices: [Point3<i32>; 3],
+    mut shader: S,
+    fb: &mut D,
+    mut depth: Option<&mut DepthBuffer>,
+) {
+    let Some((vertices, area2)) = setup_triangle(vertices, fb.limit()) else {
+        return;
+    };
+
+    let z = DepthGradient::new(&vertices, area2);
+    let max_x = fb.limit().x - 1;
+
+    scan_triangle(vertices, area2, fb.limit().y, |y, left_x, right_x| {
+        let start = left_x.max(0);
+        let end = right_x.min(max_x);
+
+        if start > end {
+            return;
+        }
+
+        let Some(depth) = depth.as_mut() else {
+            draw_run(fb, &mut shader, y, start..=end);
+            return;
+        };
+
+        // runs of pixels that pass the depth test are drawn as one span
+        let mut current_z = z.at(start, y);
+        let mut run_start = None;
+
+        for x in start..=end {
+            let visible = depth.test_and_set(Point::new(x, y), to_depth(current_z >> FP_SHIFT));
+
+            match (visible, run_start) {
+                (true, None) => run_start = Some(x),
+                (false, Some(first)) => {
+                    draw_run(fb, &mut shader, y, first..=x - 1);
+                    run_start = None;
+                }
+                _ => {}
+            }
+
+            current_z = current_z.saturating_add(z.dx);
+        }
+
+        if let Some(first) = run_start {
+            draw_run(fb, &mut shader, y, first..=end);
+        }
+    });
+}
+
+fn draw_run<C, D: GFX2DCanvas<Color = C>, S: Shader<C>>(
+    fb: &mut D,
+    shader: &mut S,
+    y: i32,
+    run: RangeInclusive<i32>,
+) {
+    let width = (run.end() - run.start() + 1) as u32;
+    shader.start_span(*run.start(), y);
+
+    fb.draw_span(
+        Point::new(*run.start(), y),
+        width,
+        run.map(|_| shader.next_pixel()),
+    );
+}
+
+// calls `span` with (y, left_x, right_x) for every visible row of the triangle
+fn scan_triangle<F: FnMut(i32, i32, i32)>(
+    vertices: [Point3<i32>; 3],
+    area2: i64,
+    height: i32,
+    mut span: F,
+) {
+    let [p1, p2, p3] = vertices;
 
     let min_y = p1.y.max(0);
-    let max_y = p3.y.min(fb.limit().y - 1);
+    let max_y = p3.y.min(height - 1);
 
     if min_y > max_y {
-        return Ok(());
+        return;
     }
 
     let short_edge_on_left = area2 < 0;
@@ -117,51 +616,34 @@ fn fill_triangle<D: GFX2DCanvas<Color = embedded_graphics_core::pixelcolor::Rgb5
 
     if p1.y != p2.y && top_start <= top_end {
         fill_section(
-            p1,
-            p3,
-            p1,
-            p2,
-            top_start,
-            top_end,
+            EdgeStepper::new(p1, p3, top_start),
+            EdgeStepper::new(p1, p2, top_start),
+            top_start..=top_end,
             short_edge_on_left,
-            color,
-            fb,
-        )?;
+            &mut span,
+        );
     }
 
     let bottom_start = p2.y.max(min_y);
     if p2.y != p3.y && bottom_start <= max_y {
         fill_section(
-            p1,
-            p3,
-            p2,
-            p3,
-            bottom_start,
-            max_y,
+            EdgeStepper::new(p1, p3, bottom_start),
+            EdgeStepper::new(p2, p3, bottom_start),
+            bottom_start..=max_y,
             short_edge_on_left,
-            color,
-            fb,
-        )?;
+            &mut span,
+        );
     }
-
-    Ok(())
 }
 
-fn fill_section<D: GFX2DCanvas<Color = embedded_graphics_core::pixelcolor::Rgb565>>(
-    long_start: Point,
-    long_end: Point,
-    short_start: Point,
-    short_end: Point,
-    y_start: i32,
-    y_end: i32,
+fn fill_section<F: FnMut(i32, i32, i32)>(
+    mut long_edge: EdgeStepper,
+    mut short_edge: EdgeStepper,
+    rows: RangeInclusive<i32>,
     short_edge_on_left: bool,
-    color: embedded_graphics_core::pixelcolor::Rgb565,
-    fb: &mut D,
-) -> Result<(), DrawError> {
-    let mut long_edge = EdgeStepper::new(long_start, long_end, y_start);
-    let mut short_edge = EdgeStepper::new(short_start, short_end, y_start);
-
-    for y in y_start..=y_end {
+    span: &mut F,
+) {
+    for y in rows {
         let long_x = long_edge.current_x();
         let short_x = short_edge.current_x();
         let (left_x, right_x) = if short_edge_on_left {
@@ -170,12 +652,107 @@ fn fill_section<D: GFX2DCanvas<Color = embedded_graphics_core::pixelcolor::Rgb56
             (long_x, short_x)
         };
 
-        fb.draw_horizontal_line(Point::new(left_x, y), Point::new(right_x, y), color)
-            .ok();
+        span(y, left_x.min(right_x), left_x.max(right_x));
 
         long_edge.advance();
         short_edge.advance();
     }
+}
 
-    Ok(())
+#[cfg(test)]
+mod tests {
+    use embedded_graphics_core::pixelcolor::{BinaryColor, Rgb565};
+
+    use super::*;
+    use crate::framebuffer::StackFramebuffer;
+
+    #[test]
+    fn beyond_the_far_plane_passes_a_cleared_depth_buffer() {
+        let z = u16::MAX as i32 + 1000;
+        let primitives = [
+            DrawPrimitive::ColoredTriangle(
+                [
+                    Point3::new(0, 0, z),
+                    Point3::new(16, 0, z),
+                    Point3::new(0, 4, z),
+                ],
+                BinaryColor::On,
+            ),
+            DrawPrimitive::Line(
+                [Point3::new(0, 5, z), Point3::new(7, 5, z)],
+                BinaryColor::On,
+            ),
+            DrawPrimitive::ColoredPoint(Point3::new(3, 7, z), BinaryColor::On),
+        ];
+
+        let mut expected = StackFramebuffer::<8, 8, _>::new(BinaryColor::Off);
+        let mut fb = StackFramebuffer::<8, 8, _>::new(BinaryColor::Off);
+        let mut buffer = [0; 64];
+        let mut depth = DepthBuffer::new(&mut buffer, 8);
+        depth.clear();
+
+        for primitive in &primitives {
+            draw(primitive, &mut expected).unwrap();
+            draw_with_depth(primitive, &mut fb, &mut depth).unwrap();
+        }
+
+        assert_eq!(
+            fb.framebuffer
+                .iter()
+                .flatten()
+                .filter(|c| c.is_on())
+                .count(),
+            39
+        );
+        assert_eq!(fb.framebuffer, expected.framebuffer);
+    }
+
+    #[test]
+    fn depth_buffer_makes_draw_order_irrelevant() {
+        let near = Rgb565::new(31, 0, 0);
+        let far = Rgb565::new(0, 0, 31);
+
+        // sloped and overlapping, the line crosses both triangles
+        let primitives = [
+            DrawPrimitive::ColoredTriangle(
+                [
+                    Point3::new(1, 1, 1000),
+                    Point3::new(14, 3, 3000),
+                    Point3::new(3, 14, 2000),
+                ],
+                near,
+            ),
+            DrawPrimitive::GouraudTriangle(
+                [
+                    Point3::new(15, 0, 4000),
+                    Point3::new(15, 15, 5000),
+                    Point3::new(0, 8, 2500),
+                ],
+                [far; 3],
+                None,
+            ),
+            DrawPrimitive::Line([Point3::new(0, 6, 6000), Point3::new(15, 6, 0)], far),
+        ];
+
+        let render = |order: [usize; 3]| {
+            let mut fb = StackFramebuffer::<16, 16, _>::new(Rgb565::new(0, 0, 0));
+            let mut buffer = [0; 256];
+            let mut depth = DepthBuffer::new(&mut buffer, 16);
+            depth.clear();
+
+            for i in order {
+                draw_with_depth(&primitives[i], &mut fb, &mut depth).unwrap();
+            }
+
+            fb.framebuffer
+        };
+
+        let forward = render([0, 1, 2]);
+        let colors = |color| forward.iter().flatten().filter(|&&c| c == color).count();
+        assert!(colors(near) > 0 && colors(far) > 0);
+
+        for order in [[2, 1, 0], [1, 0, 2], [2, 0, 1]] {
+            assert_eq!(render(order), forward, "{order:?}");
+        }
+    }
 }
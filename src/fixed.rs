@@ -0,0 +1,478 @@
+//! Q16.16 fixed-point math, used by the `fixed` feature on targets without an FPU.
+//!
+//! With the feature enabled, vertices are transformed, clip tested and projected with integer
+//! operations only, and directional lighting without a specular term is done in fixed point.
+//! Primitives that cross the near or far plane, point lights and specular highlights still go
+//! through the f32 path.
+//!
+//! # Error bound
+//!
+//! Inputs are rounded to the nearest multiple of 2⁻¹⁶ and every matrix row is accumulated in
+//! 64 bits and rounded once, so each clip space component is within
+//! `(|x| + |y| + |z| + |m0| + |m1| + |m2| + |m3| + 2) · 2⁻¹⁷` of the f32 result, where `m0..m3`
+//! are the entries of the matrix row. For a model within ±100 units and a typical projection
+//! that is below 0.003, which moves a vertex by at most `0.003 · max(width, height) / w` pixels
+//! on top of the ±1 pixel the two paths can round differently. Depth differs by at most a few
+//! units of the 16 bit depth range.
+//!
+//! Values are limited to ±32767. Vertices, matrix entries, clip space coordinates and screen
+//! positions outside that range are detected and go through the f32 path instead, like the
+//! primitives that need clipping. The arithmetic operators saturate.
+
+use core::ops::{Add, Div, Mul, Neg, Sub};
+
+use nalgebra::{Matrix4, Point3};
+
+/// Signed Q16.16 fixed-point number, operations saturate instead of wrapping
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Fixed(pub i32);
+
+impl Fixed {
+    pub const FRAC_BITS: u32 = 16;
+    pub const ZERO: Fixed = Fixed(0);
+    pub const ONE: Fixed = Fixed(1 << Self::FRAC_BITS);
+    pub const MAX: Fixed = Fixed(i32::MAX);
+    pub const MIN: Fixed = Fixed(i32::MIN);
+
+    pub const fn from_int(value: i32) -> Fixed {
+        Fixed(value.saturating_mul(1 << Self::FRAC_BITS))
+    }
+
+    /// Rounds to the nearest representable value, using integer operations only
+    pub fn from_f32(value: f32) -> Fixed {
+        let bits = value.to_bits();
+        let exponent = ((bits >> 23) & 0xff) as i32;
+        let fraction = bits & 0x7f_ffff;
+
+        // zero and subnormals, too small to be represented
+        if exponent == 0 {
+            return Fixed::ZERO;
+        }
+
+        if exponent == 0xff && fraction != 0 {
+            return Fixed::ZERO;
+        }
+
+        // value = mantissa * 2^(exponent - 150), the fixed point value is that times 2^16
+        let mantissa = (fraction | 0x80_0000) as i64;
+        let shift = exponent - 150 + Self::FRAC_BITS as i32;
+
+        let magnitude = if shift >= 0 {
+            if shift > 31 {
+                i64::MAX
+            } else {
+                mantissa << shift
+            }
+        } else if shift < -32 {
+            0
+        } else {
+            (mantissa + (1 << (-shift - 1))) >> -shift
+        };
+
+        if bits >> 31 != 0 {
+            Fixed((-magnitude).max(i32::MIN as i64) as i32)
+        } else {
+            Fixed(magnitude.min(i32::MAX as i64) as i32)
+        }
+    }
+
+    /// None for values outside of ±32768, infinities and NaN
+    pub fn checked_from_f32(value: f32) -> Option<Fixed> {
+        // a biased exponent of 127 + 15 or more is a magnitude of at least 2^15
+        if (value.to_bits() >> 23) & 0xff >= 127 + 15 {
+            return None;
+        }
+
+        Some(Fixed::from_f32(value))
+    }
+
+    pub fn to_f32(self) -> f32 {
+        self.0 as f32 / Self::ONE.0 as f32
+    }
+
+    pub fn abs(self) -> Fixed {
+        Fixed(self.0.saturating_abs())
+    }
+
+    /// Square root, zero for negative values
+    pub fn sqrt(self) -> Fixed {
+        if self.0 <= 0 {
+            return Fixed::ZERO;
+        }
+
+        // sqrt(x * 2^16) * 2^8 = sqrt(x * 2^32), with x the raw value
+        Fixed(isqrt((self.0 as u64) << Self::FRAC_BITS) as i32)
+    }
+
+    // from a 64 bit intermediate with 2 * FRAC_BITS fractional bits, rounding to nearest
+    fn from_wide(value: i64) -> Fixed {
+        saturate((value + (1 << (Self::FRAC_BITS - 1))) >> Self::FRAC_BITS)
+    }
+
+    fn checked_from_wide(value: i64) -> Option<Fixed> {
+        let value = value.checked_add(1 << (Self::FRAC_BITS - 1))? >> Self::FRAC_BITS;
+
+        i32::try_from(value).ok().map(Fixed)
+    }
+}
+
+impl Add for Fixed {
+    type Output = Fixed;
+
+    fn add(self, rhs: Fixed) -> Fixed {
+        Fixed(self.0.saturating_add(rhs.0))
+    }
+}
+
+impl Sub for Fixed {
+    type Output = Fixed;
+
+    fn sub(self, rhs: Fixed) -> Fixed {
+        Fixed(self.0.saturating_sub(rhs.0))
+    }
+}
+
+impl Neg for Fixed {
+    type Output = Fixed;
+
+    fn neg(self) -> Fixed {
+        Fixed(self.0.saturating_neg())
+    }
+}
+
+impl Mul for Fixed {
+    type Output = Fixed;
+
+    fn mul(self, rhs: Fixed) -> Fixed {
+        Fixed::from_wide(self.0 as i64 * rhs.0 as i64)
+    }
+}
+
+impl Div for Fixed {
+    type Output = Fixed;
+
+    /// Division by zero saturates towards the sign of the dividend
+    fn div(self, rhs: Fixed) -> Fixed {
+        if rhs.0 == 0 {
+            return if self.0 < 0 { Fixed::MIN } else { Fixed::MAX };
+        }
+
+        saturate(((self.0 as i64) << Self::FRAC_BITS) / rhs.0 as i64)
+    }
+}
+
+pub fn dot(a: &[Fixed; 3], b: &[Fixed; 3]) -> Fixed {
+    Fixed::from_wide(a.iter().zip(b).map(|(a, b)| a.0 as i64 * b.0 as i64).sum())
+}
+
+/// Zero for vectors too short to normalize
+pub fn normalize(v: &[Fixed; 3]) -> [Fixed; 3] {
+    // the square root of the sum of raw squares is the raw length, this can't overflow
+    let length = isqrt(v.iter().map(|c| (c.0 as i64).unsigned_abs().pow(2)).sum()) as i64;
+
+    if length == 0 {
+        return [Fixed::ZERO; 3];
+    }
+
+    v.map(|c| Fixed((((c.0 as i64) << Fixed::FRAC_BITS) / length) as i32))
+}
+
+// a dot product of raw values, None on overflow
+fn checked_dot(a: &[Fixed], b: &[Fixed]) -> Option<i64> {
+    a.iter()
+        .zip(b)
+        .try_fold(0i64, |sum, (a, b)| sum.checked_add(a.0 as i64 * b.0 as i64))
+}
+
+/// Affine or projective 4x4 matrix in fixed point, converted once per mesh
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FixedMatrix4 {
+    rows: [[Fixed; 4]; 4],
+}
+
+impl FixedMatrix4 {
+    /// None if an entry is out of range
+    pub fn from_matrix(matrix: &Matrix4<f32>) -> Option<FixedMatrix4> {
+        let mut rows = [[Fixed::ZERO; 4]; 4];
+
+        for (r, row) in rows.iter_mut().enumerate() {
+            for (c, entry) in row.iter_mut().enumerate() {
+                *entry = Fixed::checked_from_f32(matrix[(r, c)])?;
+            }
+        }
+
+        Some(FixedMatrix4 { rows })
+    }
+
+    /// Transforms a point with w = 1, giving homogeneous coordinates.
+    ///
+    /// None if the point or the result is out of range.
+    pub fn transform_point(&self, point: &[f32; 3]) -> Option<[Fixed; 4]> {
+        let point = [
+            Fixed::checked_from_f32(point[0])?,
+            Fixed::checked_from_f32(point[1])?,
+            Fixed::checked_from_f32(point[2])?,
+            Fixed::ONE,
+        ];
+
+        let [x, y, z, w] = self
+            .rows
+            .map(|row| checked_dot(&row, &point).and_then(Fixed::checked_from_wide));
+
+        Some([x?, y?, z?, w?])
+    }
+
+    /// Transforms a direction by the upper 3x3 part, without translation.
+    ///
+    /// None if the vector or the result is out of range.
+    pub fn transform_vector(&self, vector: &[f32; 3]) -> Option<[Fixed; 3]> {
+        let vector = [
+            Fixed::checked_from_f32(vector[0])?,
+            Fixed::checked_from_f32(vector[1])?,
+            Fixed::checked_from_f32(vector[2])?,
+        ];
+
+        let [x, y, z] = [0, 1, 2]
+            .map(|r| checked_dot(&self.rows[r][..3], &vector).and_then(Fixed::checked_from_wide));
+
+        Some([x?, y?, z?])
+    }
+}
+
+/// Perspective divide and viewport transform, the same mapping as the f32 path.
+///
+/// Returns None for points on or behind the eye, which have to be clipped first, and for screen
+/// positions that don't fit in an i32.
+pub fn project(point: &[Fixed; 4], width: u16, height: u16) -> Option<Point3<i32>> {
+    let [x, y, z, w] = point.map(|c| c.0 as i64);
+
+    if w <= 0 {
+        return None;
+    }
+
+    // normalized device coordinates with 16 fractional bits, not limited to [-1, 1]
+    let one = Fixed::ONE.0 as i64;
+    let ndc = |c: i64| (c << Fixed::FRAC_BITS) / w;
+
+    // divided instead of shifted, so it truncates towards zero like the f32 path
+    let viewport = |ndc: i64, size: i64| i32::try_from(ndc.checked_mul(size)? / (2 * one)).ok();
+
+    Some(Point3::new(
+        viewport(one + ndc(x), width as i64)?,
+        viewport(one - ndc(y), height as i64)?,
+        viewport(one + ndc(z), u16::MAX as i64)?,
+    ))
+}
+
+fn saturate(value: i64) -> Fixed {
+    Fixed(value.clamp(i32::MIN as i64, i32::MAX as i64) as i32)
+}
+
+// floor of the square root
+fn isqrt(value: u64) -> u64 {
+    let mut remainder = value;
+    let mut root = 0;
+    let mut bit = 1 << 62;
+
+    while bit > value {
+        bit >>= 2;
+    }
+
+    while bit != 0 {
+        if remainder >= root + bit {
+            remainder -= root + bit;
+            root = (root >> 1) + bit;
+        } else {
+            root >>= 1;
+        }
+        bit >>= 2;
+    }
+
+    root
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::{Matrix4, Point3, Vector3};
+
+    use super::*;
+
+    // deterministic values in -range..range
+    struct Lcg(u32);
+
+    impl Lcg {
+        fn next(&mut self, range: f32) -> f32 {
+            self.0 = self.0.wrapping_mul(1664525).wrapping_add(1013904223);
+            ((self.0 >> 8) as f32 / (1 << 24) as f32 * 2.0 - 1.0) * range
+        }
+    }
+
+    fn matrices() -> [Matrix4<f32>; 3] {
+        let projection = Matrix4::new_perspective(1.25, 1.0, 0.4, 20.0);
+        let view = Matrix4::look_at_rh(
+            &Point3::new(4.0, 3.0, 5.0),
+            &Point3::origin(),
+            &Vector3::y(),
+        );
+        let model = Matrix4::new_translation(&Vector3::new(2.0, -1.0, 0.5))
+            * Matrix4::from_euler_angles(0.3, 1.1, -0.7)
+            * Matrix4::new_scaling(1.5);
+
+        [model, view * model, projection * view * model]
+    }
+
+    #[test]
+    fn from_f32_rounds_to_nearest() {
+        assert_eq!(Fixed::from_f32(1.5), Fixed(0x18000));
+        assert_eq!(Fixed::from_f32(-2.25), Fixed(-0x24000));
+        assert_eq!(Fixed::from_f32(1.0 / 131072.0 * 3.0), Fixed(2));
+        assert_eq!(Fixed::from_f32(1e-9), Fixed::ZERO);
+        assert_eq!(Fixed::from_f32(1e9), Fixed::MAX);
+    }
+
+    #[test]
+    fn checked_from_f32_rejects_out_of_range() {
+        assert_eq!(
+            Fixed::checked_from_f32(32767.5),
+            Some(Fixed::from_f32(32767.5))
+        );
+        assert_eq!(
+            Fixed::checked_from_f32(-32767.5),
+            Some(Fixed::from_f32(-32767.5))
+        );
+
+        for value in [32768.0, -32768.0, 1e9, f32::INFINITY, f32::NAN] {
+            assert_eq!(Fixed::checked_from_f32(value), None, "{value}");
+        }
+    }
+
+    #[test]
+    fn transform_point_is_within_the_error_bound() {
+        let mut rng = Lcg(1);
+
+        for matrix in matrices() {
+            let fixed = FixedMatrix4::from_matrix(&matrix).unwrap();
+
+            for _ in 0..1000 {
+                let point = [rng.next(100.0), rng.next(100.0), rng.next(100.0)];
+                let result = fixed.transform_point(&point).unwrap();
+
+                for (r, component) in result.iter().enumerate() {
+                    let row = [0, 1, 2, 3].map(|c| matrix[(r, c)] as f64);
+                    let exact = row[0] * point[0] as f64
+                        + row[1] * point[1] as f64
+                        + row[2] * point[2] as f64
+                        + row[3];
+
+                    let bound = (point.iter().map(|v| v.abs() as f64).sum::<f64>()
+                        + row.iter().map(|m| m.abs()).sum::<f64>()
+                        + 2.0)
+                        / (1 << 17) as f64;
+
+                    let error = (component.to_f32() as f64 - exact).abs();
+                    assert!(error <= bound, "{error} > {bound} for {point:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn transform_vector_matches_f32() {
+        let mut rng = Lcg(2);
+        let matrix = matrices()[0];
+        let fixed = FixedMatrix4::from_matrix(&matrix).unwrap();
+
+        for _ in 0..1000 {
+            let vector = Vector3::new(rng.next(1.0), rng.next(1.0), rng.next(1.0));
+            let expected = matrix.transform_vector(&vector);
+            let result = fixed.transform_vector(&vector.into()).unwrap();
+
+            for i in 0..3 {
+                assert!((result[i].to_f32() - expected[i]).abs() < 1e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn transforms_out_of_range_are_rejected() {
+        let identity = FixedMatrix4::from_matrix(&Matrix4::identity()).unwrap();
+        assert_eq!(identity.transform_point(&[40000.0, 0.0, 0.0]), None);
+        assert_eq!(identity.transform_vector(&[0.0, f32::NAN, 0.0]), None);
+
+        // inputs in range, results out of range
+        let scaling = FixedMatrix4::from_matrix(&Matrix4::new_scaling(1000.0)).unwrap();
+        assert_eq!(scaling.transform_point(&[100.0, 0.0, 0.0]), None);
+        assert_eq!(scaling.transform_vector(&[0.0, 0.0, -100.0]), None);
+        assert!(scaling.transform_point(&[10.0, 0.0, 0.0]).is_some());
+
+        assert!(FixedMatrix4::from_matrix(&Matrix4::new_scaling(1e6)).is_none());
+    }
+
+    #[test]
+    fn project_matches_f32() {
+        let mut rng = Lcg(3);
+        let (width, height) = (320, 240);
+
+        for _ in 0..1000 {
+            let w = 0.5 + rng.next(1.0).abs() * 50.0;
+            let point = [rng.next(w), rng.next(w), rng.next(w), w].map(Fixed::from_f32);
+            let [x, y, z, w] = point.map(Fixed::to_f32);
+
+            let result = project(&point, width, height).unwrap();
+
+            let expected = [
+                ((1.0 + x / w) * 0.5 * width as f32) as i32,
+                ((1.0 - y / w) * 0.5 * height as f32) as i32,
+                ((z / w + 1.0) * 0.5 * u16::MAX as f32) as i32,
+            ];
+
+            assert!(
+                (result.x - expected[0]).abs() <= 1,
+                "{result:?} {expected:?}"
+            );
+            assert!(
+                (result.y - expected[1]).abs() <= 1,
+                "{result:?} {expected:?}"
+            );
+            assert!(
+                (result.z - expected[2]).abs() <= 2,
+                "{result:?} {expected:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn project_rejects_overflow() {
+        // on the eye
+        assert_eq!(
+            project(
+                &[Fixed::ONE, Fixed::ONE, Fixed::ZERO, Fixed::ZERO],
+                100,
+                100
+            ),
+            None
+        );
+
+        // far outside of the screen, the screen position doesn't fit in an i32
+        let point = [Fixed::MAX, Fixed::ZERO, Fixed::ZERO, Fixed(1)];
+        assert_eq!(project(&point, u16::MAX, u16::MAX), None);
+    }
+
+    #[test]
+    fn normalize_long_and_short_vectors() {
+        for v in [
+            [1000.0, 0.0, 0.0],
+            [20000.0, -20000.0, 20000.0],
+            [0.01, 0.02, -0.02],
+        ] {
+            let normal = normalize(&v.map(Fixed::from_f32));
+            let expected = Vector3::from(v).normalize();
+
+            for i in 0..3 {
+                assert!((normal[i].to_f32() - expected[i]).abs() < 1e-3, "{v:?}");
+            }
+        }
+
+        assert_eq!(normalize(&[Fixed::ZERO; 3]), [Fixed::ZERO; 3]);
+    }
+}
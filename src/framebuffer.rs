@@ -1,22 +1,25 @@
 use embedded_graphics_core::{
     draw_target::DrawTarget,
-    geometry::{OriginDimensions, Point},
+    geometry::{OriginDimensions, Point, Size},
     pixelcolor::{IntoStorage, Rgb565},
-    prelude::PixelColor,
+    prelude::{Pixel, PixelColor},
+    primitives::Rectangle,
 };
 
 use crate::canvas::GFX2DCanvas;
 
 pub trait RawFramebuffer {
-    fn set_pixel(&mut self, point: Point, color: Rgb565) -> bool;
-    fn set_pixel_unchecked(&mut self, point: Point, color: Rgb565);
+    type Color: PixelColor;
+
+    fn set_pixel(&mut self, point: Point, color: Self::Color) -> bool;
+    fn set_pixel_unchecked(&mut self, point: Point, color: Self::Color);
     fn limit(&self) -> Point;
 
     fn is_in_bounds(&self, point: &Point) -> bool {
         point.x >= 0 && point.x < self.limit().x && point.y >= 0 && point.y < self.limit().y
     }
 
-    //fn get_continuous(&self) -> impl IntoIterator<Item = Rgb565>;
+    //fn get_continuous(&self) -> impl IntoIterator<Item = Self::Color>;
 }
 
 // ------------------------------
@@ -33,12 +36,17 @@ impl<const W: usize, const H: usize, Color: PixelColor> StackFramebuffer<W, H, C
     }
 }
 
-impl<const W: usize, const H: usize> GFX2DCanvas for StackFramebuffer<W, H, Rgb565> {
-    type Color = Rgb565;
+impl<const W: usize, const H: usize, Color: PixelColor> GFX2DCanvas
+    for StackFramebuffer<W, H, Color>
+{
 }
 
-impl<const W: usize, const H: usize> RawFramebuffer for StackFramebuffer<W, H, Rgb565> {
-    fn set_pixel(&mut self, point: Point, color: Rgb565) -> bool {
+impl<const W: usize, const H: usize, Color: PixelColor> RawFramebuffer
+    for StackFramebuffer<W, H, Color>
+{
+    type Color = Color;
+
+    fn set_pixel(&mut self, point: Point, color: Color) -> bool {
         if self.is_in_bounds(&point) {
             self.framebuffer[point.y as usize][point.x as usize] = color;
             true
@@ -47,7 +55,7 @@ impl<const W: usize, const H: usize> RawFramebuffer for StackFramebuffer<W, H, R
         }
     }
 
-    fn set_pixel_unchecked(&mut self, point: Point, color: Rgb565) {
+    fn set_pixel_unchecked(&mut self, point: Point, color: Color) {
         self.framebuffer[point.y as usize][point.x as usize] = color;
     }
 
@@ -64,8 +72,10 @@ impl<const W: usize, const H: usize, Color: PixelColor> OriginDimensions
     }
 }
 
-impl<const W: usize, const H: usize> DrawTarget for StackFramebuffer<W, H, Rgb565> {
-    type Color = Rgb565;
+impl<const W: usize, const H: usize, Color: PixelColor> DrawTarget
+    for StackFramebuffer<W, H, Color>
+{
+    type Color = Color;
     type Error = core::convert::Infallible;
 
     fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
@@ -86,11 +96,11 @@ pub struct DmaReadyFramebuffer<const W: usize, const H: usize> {
     big_endian: bool,
 }
 
-impl<const W: usize, const H: usize> GFX2DCanvas for DmaReadyFramebuffer<W, H> {
-    type Color = Rgb565;
-}
+impl<const W: usize, const H: usize> GFX2DCanvas for DmaReadyFramebuffer<W, H> {}
 
 impl<const W: usize, const H: usize> RawFramebuffer for DmaReadyFramebuffer<W, H> {
+    type Color = Rgb565;
+
     fn set_pixel(&mut self, point: Point, color: Rgb565) -> bool {
         if self.is_in_bounds(&point) {
             self.raw_set_pixel(point, color);
@@ -181,3 +191,151 @@ impl<const W: usize, const H: usize> OriginDimensions for DmaReadyFramebuffer<W,
         embedded_graphics_core::geometry::Size::new(W as u32, H as u32)
     }
 }
+
+// ------------------------------
+
+/// Renders to any `embedded-graphics` `DrawTarget`, such as a display driver.
+///
+/// Horizontal lines and triangle spans are sent with `fill_solid` and `fill_contiguous`, so
+/// drivers that implement those can push a whole span in one transfer. Errors from the target
+/// don't stop drawing, the first one is kept for `take_error`.
+pub struct DrawTargetCanvas<D: DrawTarget> {
+    target: D,
+    area: Rectangle,
+    error: Option<D::Error>,
+}
+
+impl<D: DrawTarget> DrawTargetCanvas<D> {
+    pub fn new(target: D) -> DrawTargetCanvas<D> {
+        DrawTargetCanvas {
+            area: target.bounding_box(),
+            target,
+            error: None,
+        }
+    }
+
+    pub fn target(&self) -> &D {
+        &self.target
+    }
+
+    pub fn target_mut(&mut self) -> &mut D {
+        &mut self.target
+    }
+
+    pub fn into_inner(self) -> D {
+        self.target
+    }
+
+    /// The first error returned by the target since the last call
+    pub fn take_error(&mut self) -> Option<D::Error> {
+        self.error.take()
+    }
+
+    fn keep_error(&mut self, result: Result<(), D::Error>) {
+        if let Err(error) = result {
+            self.error.get_or_insert(error);
+        }
+    }
+}
+
+impl<D: DrawTarget> GFX2DCanvas for DrawTargetCanvas<D> {
+    fn fill_span(&mut self, start: Point, width: u32, color: D::Color) {
+        let span = Rectangle::new(self.area.top_left + start, Size::new(width, 1));
+        let result = self.target.fill_solid(&span, color);
+        self.keep_error(result);
+    }
+
+    fn draw_span<I: IntoIterator<Item = D::Color>>(&mut self, start: Point, width: u32, colors: I) {
+        let span = Rectangle::new(self.area.top_left + start, Size::new(width, 1));
+        let result = self.target.fill_contiguous(&span, colors);
+        self.keep_error(result);
+    }
+}
+
+impl<D: DrawTarget> RawFramebuffer for DrawTargetCanvas<D> {
+    type Color = D::Color;
+
+    fn set_pixel(&mut self, point: Point, color: D::Color) -> bool {
+        if self.is_in_bounds(&point) {
+            self.set_pixel_unchecked(point, color);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn set_pixel_unchecked(&mut self, point: Point, color: D::Color) {
+        let pixel = Pixel(self.area.top_left + point, color);
+        let result = self.target.draw_iter(core::iter::once(pixel));
+        self.keep_error(result);
+    }
+
+    // canvas coordinates start at the top left corner of the target's bounding box
+    fn limit(&self) -> Point {
+        Point::new(self.area.size.width as i32, self.area.size.height as i32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_graphics_core::pixelcolor::{Gray8, GrayColor};
+
+    use super::*;
+
+    // a 4x2 target that records the area of every fill_solid call
+    struct Recording {
+        fills: [Option<Rectangle>; 4],
+        count: usize,
+    }
+
+    impl OriginDimensions for Recording {
+        fn size(&self) -> Size {
+            Size::new(4, 2)
+        }
+    }
+
+    impl DrawTarget for Recording {
+        type Color = Gray8;
+        type Error = core::convert::Infallible;
+
+        fn draw_iter<I>(&mut self, _pixels: I) -> Result<(), Self::Error>
+        where
+            I: IntoIterator<Item = Pixel<Gray8>>,
+        {
+            Ok(())
+        }
+
+        fn fill_solid(&mut self, area: &Rectangle, _color: Gray8) -> Result<(), Self::Error> {
+            self.fills[self.count] = Some(*area);
+            self.count += 1;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn off_screen_spans_are_not_sent() {
+        let mut canvas = DrawTargetCanvas::new(Recording {
+            fills: [None; 4],
+            count: 0,
+        });
+
+        for (p1, p2) in [(-5, -1), (4, 9), (-3, 1), (2, 7)] {
+            canvas
+                .draw_horizontal_line(Point::new(p1, 1), Point::new(p2, 1), Gray8::WHITE)
+                .unwrap();
+        }
+        assert!(canvas
+            .draw_horizontal_line(Point::new(0, 2), Point::new(3, 2), Gray8::WHITE)
+            .is_err());
+
+        let target = canvas.into_inner();
+        assert_eq!(target.count, 2);
+        assert_eq!(
+            target.fills[..2],
+            [
+                Some(Rectangle::new(Point::new(0, 1), Size::new(2, 1))),
+                Some(Rectangle::new(Point::new(2, 1), Size::new(2, 1))),
+            ]
+        );
+    }
+}
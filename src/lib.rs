@@ -1,219 +1,850 @@
+#![no_std]
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
+
+use bounds::Frustum;
 use camera::Camera;
+use clip::ClipPlane;
+use color::ShadeColor;
+use core::borrow::Borrow;
 use embedded_graphics_core::pixelcolor::Rgb565;
-use embedded_graphics_core::pixelcolor::RgbColor;
+#[cfg(feature = "fixed")]
+use fixed::{Fixed, FixedMatrix4};
+use lighting::Lighting;
+use material::Material;
+use mesh::CullMode;
+use mesh::Interpolation;
 use mesh::K3dMesh;
 use mesh::RenderMode;
 use nalgebra::Matrix4;
-use nalgebra::Point2;
 use nalgebra::Point3;
+use nalgebra::SVector;
+use nalgebra::Vector2;
 use nalgebra::Vector3;
+use nalgebra::Vector4;
+use ray::PickHit;
+use ray::Ray;
+use texture::Texture;
 
+pub mod bounds;
 pub mod camera;
 pub mod canvas;
+mod clip;
+pub mod color;
+pub mod depth;
+pub mod dirty;
+pub mod dither;
 pub mod draw;
+#[cfg(feature = "fixed")]
+pub mod fixed;
 pub mod framebuffer;
+pub mod lighting;
+pub mod material;
 pub mod mesh;
+pub mod normals;
+pub mod orbit;
+#[cfg(feature = "std")]
 pub mod perfcounter;
+pub mod ray;
+pub mod shapes;
+pub mod texture;
 
+/// A screen space primitive, `C` is the color of the render target
 #[derive(Debug)]
-pub enum DrawPrimitive {
-    ColoredPoint(Point2<i32>, Rgb565),
-    Line([Point2<i32>; 2], Rgb565),
-    ColoredTriangle([Point2<i32>; 3], Rgb565),
+pub enum DrawPrimitive<'a, C = Rgb565> {
+    ColoredPoint(Point3<i32>, C),
+    Line([Point3<i32>; 2], C),
+    ColoredTriangle([Point3<i32>; 3], C),
+    /// The optional 1/w of each vertex enables perspective correct interpolation
+    GouraudTriangle([Point3<i32>; 3], [C; 3], Option<[f32; 3]>),
+    TexturedTriangle(
+        [Point3<i32>; 3],
+        [[f32; 2]; 3],
+        Texture<'a>,
+        Option<[f32; 3]>,
+    ),
+}
+
+impl<C> DrawPrimitive<'_, C> {
+    /// Sort key for the painter's algorithm, larger values are further away
+    pub fn depth(&self, sort: DepthSort) -> i32 {
+        let vertices: &[Point3<i32>] = match self {
+            DrawPrimitive::ColoredPoint(point, _) => core::slice::from_ref(point),
+            DrawPrimitive::Line(line, _) => line,
+            DrawPrimitive::ColoredTriangle(triangle, _)
+            | DrawPrimitive::GouraudTriangle(triangle, _, _)
+            | DrawPrimitive::TexturedTriangle(triangle, _, _, _) => triangle,
+        };
+
+        match sort {
+            // summed in i64, z can saturate to i32::MAX without far clipping
+            DepthSort::Average => {
+                (vertices.iter().map(|v| v.z as i64).sum::<i64>() / vertices.len() as i64) as i32
+            }
+            DepthSort::Max => vertices.iter().map(|v| v.z).max().unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DepthSort {
+    #[default]
+    Average,
+    // fewer artifacts with long triangles that reach behind their neighbours
+    Max,
+}
+
+/// The sort buffer was too small, the primitives that did fit were still drawn
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SortOverflow {
+    // buffer length needed to hold the whole frame
+    pub required: usize,
+    pub dropped: usize,
+}
+
+// model to clip space transform of a mesh, converted to fixed point once per mesh, the fixed
+// matrices are None when an entry is out of range
+struct MeshTransform {
+    clip: Matrix4<f32>,
+    #[cfg(feature = "fixed")]
+    fixed_clip: Option<FixedMatrix4>,
+    #[cfg(feature = "fixed")]
+    fixed_model: Option<FixedMatrix4>,
+}
+
+impl MeshTransform {
+    #[cfg_attr(not(feature = "fixed"), allow(unused_variables))]
+    fn new(clip: Matrix4<f32>, model: &Matrix4<f32>) -> MeshTransform {
+        MeshTransform {
+            clip,
+            #[cfg(feature = "fixed")]
+            fixed_clip: FixedMatrix4::from_matrix(&clip),
+            #[cfg(feature = "fixed")]
+            fixed_model: FixedMatrix4::from_matrix(model),
+        }
+    }
+
+    // None where the f32 path has to be used
+    #[cfg(feature = "fixed")]
+    fn fixed_clip_point(&self, point: &[f32; 3]) -> Option<[Fixed; 4]> {
+        self.fixed_clip.as_ref()?.transform_point(point)
+    }
 }
 
 pub struct K3dengine {
     pub camera: Camera,
+    pub lighting: Lighting,
     width: u16,
     height: u16,
+    far_clipping: bool,
 }
 
 impl K3dengine {
     pub fn new(width: u16, height: u16) -> K3dengine {
         K3dengine {
             camera: Camera::new(width as f32 / height as f32),
+            lighting: Lighting::new(),
             width,
             height,
+            far_clipping: true,
         }
     }
 
-    fn transform_point(&self, point: &[f32; 3], model_matrix: Matrix4<f32>) -> Option<Point3<i32>> {
-        let point = nalgebra::Vector4::new(point[0], point[1], point[2], 1.0);
-        let point = model_matrix * point;
+    pub fn set_far_clipping(&mut self, enabled: bool) {
+        self.far_clipping = enabled;
+    }
 
-        if point.w < 0.0 {
-            return None;
+    fn clip_planes(&self) -> &'static [ClipPlane] {
+        if self.far_clipping {
+            &[ClipPlane::Near, ClipPlane::Far]
+        } else {
+            &[ClipPlane::Near]
         }
-        if point.z < self.camera.near || point.z > self.camera.far {
-            return None;
+    }
+
+    fn frustum(&self, transform_matrix: &Matrix4<f32>) -> Frustum {
+        let frustum = Frustum::from_matrix(transform_matrix);
+
+        if self.far_clipping {
+            frustum
+        } else {
+            frustum.without_far_plane()
         }
+    }
 
-        let point = Point3::from_homogeneous(point)?;
+    fn to_clip_space(point: &[f32; 3], model_matrix: &Matrix4<f32>) -> Vector4<f32> {
+        model_matrix * Vector4::new(point[0], point[1], point[2], 1.0)
+    }
 
-        Some(Point3::new(
+    // perspective divide and viewport transform, `point` must be in front of the near plane
+    fn project(&self, point: &Vector4<f32>) -> Point3<i32> {
+        let point = point.xyz() / point.w;
+
+        Point3::new(
             ((1.0 + point.x) * 0.5 * self.width as f32) as i32,
             ((1.0 - point.y) * 0.5 * self.height as f32) as i32,
-            (point.z * (self.camera.far - self.camera.near) + self.camera.near) as i32,
-        ))
+            // depth, mapped from [-1, 1] to the range of a 16 bit depth buffer
+            ((point.z + 1.0) * 0.5 * u16::MAX as f32) as i32,
+        )
+    }
+
+    // screen position of a point that needs no clipping, None where the f32 path has to be used
+    #[cfg(feature = "fixed")]
+    fn project_fixed(&self, point: &[Fixed; 4]) -> Option<Point3<i32>> {
+        if !clip::is_inside_fixed(point, self.clip_planes()) {
+            return None;
+        }
+
+        fixed::project(point, self.width, self.height)
+    }
+
+    fn transform_point(&self, point: &[f32; 3], transform: &MeshTransform) -> Option<Point3<i32>> {
+        #[cfg(feature = "fixed")]
+        if let Some(point) = transform.fixed_clip_point(point) {
+            if !clip::is_inside_fixed(&point, self.clip_planes()) {
+                return None;
+            }
+
+            if let Some(point) = fixed::project(&point, self.width, self.height) {
+                return Some(point);
+            }
+        }
+
+        let point = Self::to_clip_space(point, &transform.clip);
+
+        if !clip::is_inside(&point, self.clip_planes()) {
+            return None;
+        }
+
+        Some(self.project(&point))
     }
 
-    fn transform_points<const N: usize>(
+    fn transform_line(
         &self,
-        indices: &[usize; N],
+        line: &[usize; 2],
         vertices: &[[f32; 3]],
-        model_matrix: Matrix4<f32>,
-    ) -> Option<[Point3<i32>; N]> {
-        let mut ret = [Point3::new(0, 0, 0); N];
+        transform: &MeshTransform,
+    ) -> Option<[Point3<i32>; 2]> {
+        #[cfg(feature = "fixed")]
+        if let [Some(p1), Some(p2)] = line.map(|i| {
+            transform
+                .fixed_clip_point(&vertices[i])
+                .and_then(|p| self.project_fixed(&p))
+        }) {
+            return Some([p1, p2]);
+        }
+
+        let line = line.map(|i| Self::to_clip_space(&vertices[i], &transform.clip));
+
+        let [p1, p2] = clip::clip_line(line, self.clip_planes())?;
+
+        Some([self.project(&p1), self.project(&p2)])
+    }
+
+    fn transform_triangle<F: FnMut([Point3<i32>; 3])>(
+        &self,
+        face: &[usize; 3],
+        vertices: &[[f32; 3]],
+        transform: &MeshTransform,
+        cull: CullMode,
+        mut emit: F,
+    ) {
+        #[cfg(feature = "fixed")]
+        if let [Some(c1), Some(c2), Some(c3)] =
+            face.map(|i| transform.fixed_clip_point(&vertices[i]))
+        {
+            let clip = [c1, c2, c3];
+
+            if let [Some(p1), Some(p2), Some(p3)] = clip.map(|p| self.project_fixed(&p)) {
+                if !cull.culls(is_front_facing_fixed(&clip)) {
+                    emit([p1, p2, p3]);
+                }
+                return;
+            }
+        }
+
+        let face = face.map(|i| Self::to_clip_space(&vertices[i], &transform.clip));
+
+        clip::clip_triangle(face, self.clip_planes(), |triangle| {
+            if cull.culls(is_front_facing(&triangle)) {
+                return;
+            }
+
+            emit(triangle.map(|p| self.project(&p)))
+        });
+    }
+
+    // clips and projects a triangle, interpolating per-vertex attributes along with it. The 1/w
+    // of each vertex is only computed for perspective correct interpolation
+    fn transform_attributed_triangle<const N: usize, F>(
+        &self,
+        positions: [&[f32; 3]; 3],
+        attributes: [SVector<f32, N>; 3],
+        transform: &MeshTransform,
+        cull: CullMode,
+        perspective_correct: bool,
+        mut emit: F,
+    ) where
+        F: FnMut([Point3<i32>; 3], [SVector<f32, N>; 3], Option<[f32; 3]>),
+    {
+        #[cfg(feature = "fixed")]
+        if let [Some(c1), Some(c2), Some(c3)] = positions.map(|p| transform.fixed_clip_point(p)) {
+            let clip = [c1, c2, c3];
+
+            if let [Some(p1), Some(p2), Some(p3)] = clip.map(|p| self.project_fixed(&p)) {
+                if !cull.culls(is_front_facing_fixed(&clip)) {
+                    // perspective correct interpolation is done in f32
+                    let inv_w = perspective_correct.then(|| clip.map(|p| 1.0 / p[3].to_f32()));
+                    emit([p1, p2, p3], attributes, inv_w);
+                }
+                return;
+            }
+        }
+
+        let face = [0, 1, 2].map(|i| {
+            (
+                Self::to_clip_space(positions[i], &transform.clip),
+                attributes[i],
+            )
+        });
+
+        clip::clip_triangle(face, self.clip_planes(), |triangle| {
+            if cull.culls(is_front_facing(&triangle.map(|(p, _)| p))) {
+                return;
+            }
 
-        for i in 0..N {
-            ret[i] = self.transform_point(&vertices[indices[i]], model_matrix)?;
+            emit(
+                triangle.map(|(p, _)| self.project(&p)),
+                triangle.map(|(_, attributes)| attributes),
+                perspective_correct.then(|| triangle.map(|(p, _)| 1.0 / p.w)),
+            )
+        });
+    }
+
+    // lighting of a point in world space, returns the color unchanged for the unlit modes
+    fn shade(
+        &self,
+        render_mode: &RenderMode,
+        material: &Material,
+        color: Vector3<f32>,
+        position: &Point3<f32>,
+        normal: Option<Vector3<f32>>,
+    ) -> Vector3<f32> {
+        let Some(normal) = normal.filter(|_| !material.unlit) else {
+            return color;
+        };
+
+        let normal = normalize_or_zero(normal);
+        let view = self.camera.view_vector(position);
+
+        let lit = match render_mode {
+            RenderMode::SolidLightDir(direction) | RenderMode::GouraudLightDir(direction) => self
+                .lighting
+                .shade_directional(color, material, &normal, direction, &view),
+            RenderMode::SolidLit | RenderMode::GouraudLit => self
+                .lighting
+                .shade(color, material, position, &normal, &view),
+            _ => return color,
+        };
+
+        if material.emissive {
+            lit + color
+        } else {
+            lit
         }
+    }
 
-        Some(ret)
+    // shade() for a position and normal in the model space of `mesh`
+    fn shade_model(
+        &self,
+        mesh: &K3dMesh,
+        material: &Material,
+        color: Rgb565,
+        position: &Point3<f32>,
+        normal: Option<Vector3<f32>>,
+    ) -> Vector3<f32> {
+        self.shade(
+            &mesh.render_mode,
+            material,
+            color.to_linear(),
+            &mesh.model_matrix.transform_point(position),
+            normal.map(|n| mesh.model_matrix.transform_vector(&n)),
+        )
     }
 
-    pub fn render<'a, MS, F>(&self, meshes: MS, mut callback: F)
+    // flat shading, lit at the center of the face
+    #[cfg_attr(not(feature = "fixed"), allow(unused_variables))]
+    fn shade_face<C: ShadeColor>(
+        &self,
+        mesh: &K3dMesh,
+        material: &Material,
+        face: &[usize; 3],
+        normal: Option<Vector3<f32>>,
+        transform: &MeshTransform,
+    ) -> C {
+        #[cfg(feature = "fixed")]
+        if let Some(color) = self.shade_fixed(
+            &mesh.render_mode,
+            material,
+            material.color,
+            normal,
+            transform.fixed_model.as_ref(),
+        ) {
+            return C::from_linear_fixed(color);
+        }
+
+        let vertices = mesh.geometry().vertices;
+        let center = face
+            .iter()
+            .map(|&v| Vector3::from(vertices[v]))
+            .sum::<Vector3<f32>>()
+            / 3.0;
+
+        C::from_linear(self.shade_model(mesh, material, material.color, &center.into(), normal))
+    }
+
+    // shade() in fixed point, None for point lights and specular highlights which need f32
+    #[cfg(feature = "fixed")]
+    fn shade_fixed(
+        &self,
+        render_mode: &RenderMode,
+        material: &Material,
+        color: Rgb565,
+        normal: Option<Vector3<f32>>,
+        model: Option<&FixedMatrix4>,
+    ) -> Option<[Fixed; 3]> {
+        let color = color.to_linear_fixed();
+
+        let Some(normal) = normal.filter(|_| !material.unlit) else {
+            return Some(color);
+        };
+
+        let direction = match render_mode {
+            RenderMode::SolidLightDir(direction) | RenderMode::GouraudLightDir(direction) => {
+                direction
+            }
+            RenderMode::SolidLit | RenderMode::GouraudLit => return None,
+            _ => return Some(color),
+        };
+
+        if material.has_specular() {
+            return None;
+        }
+
+        let normal = fixed::normalize(&model?.transform_vector(&normal.into())?);
+        let direction = [direction.x, direction.y, direction.z].map(Fixed::from_f32);
+
+        let lit = self
+            .lighting
+            .shade_directional_fixed(color, material, &normal, &direction);
+
+        if material.emissive {
+            Some([0, 1, 2].map(|i| lit[i] + color[i]))
+        } else {
+            Some(lit)
+        }
+    }
+
+    /// Renders to any color type, mesh colors are converted from `Rgb565`
+    pub fn render<'a, C, MS, F>(&self, meshes: MS, mut callback: F)
     where
-        MS: IntoIterator<Item = K3dMesh<'a>>,
-        F: FnMut(DrawPrimitive),
+        C: ShadeColor,
+        MS: IntoIterator,
+        MS::Item: Borrow<K3dMesh<'a>>,
+        F: FnMut(DrawPrimitive<'a, C>),
     {
         for mesh in meshes {
-            if mesh.geometry.vertices.is_empty() {
+            let mesh = mesh.borrow();
+            let geometry = mesh.geometry();
+
+            if geometry.vertices.is_empty() {
                 continue;
             }
 
             let transform_matrix = self.camera.vp_matrix * mesh.model_matrix;
 
+            //Frustum culling
+            if let Some(bounds) = &geometry.bounds {
+                if !self.frustum(&transform_matrix).intersects(bounds) {
+                    continue;
+                }
+            }
+
+            let transform = MeshTransform::new(transform_matrix, &mesh.model_matrix);
+            let color = C::from(mesh.material.color);
+
             match mesh.render_mode {
                 RenderMode::Points => {
-                    let screen_space_points = mesh
-                        .geometry
+                    let screen_space_points = geometry
                         .vertices
                         .iter()
-                        .filter_map(|v| self.transform_point(v, transform_matrix));
+                        .filter_map(|v| self.transform_point(v, &transform));
 
-                    if mesh.geometry.colors.len() == mesh.geometry.vertices.len() {
-                        for (point, color) in screen_space_points.zip(mesh.geometry.colors) {
-                            callback(DrawPrimitive::ColoredPoint(point.xy(), *color));
+                    if geometry.colors.len() == geometry.vertices.len() {
+                        for (point, color) in screen_space_points.zip(geometry.colors) {
+                            callback(DrawPrimitive::ColoredPoint(point, C::from(*color)));
                         }
                     } else {
                         for point in screen_space_points {
-                            callback(DrawPrimitive::ColoredPoint(point.xy(), mesh.color));
+                            callback(DrawPrimitive::ColoredPoint(point, color));
                         }
                     }
                 }
 
-                RenderMode::Lines if !mesh.geometry.lines.is_empty() => {
-                    for line in mesh.geometry.lines {
+                RenderMode::Lines if !geometry.lines.is_empty() => {
+                    for line in geometry.lines {
                         if let Some([p1, p2]) =
-                            self.transform_points(line, mesh.geometry.vertices, transform_matrix)
+                            self.transform_line(line, geometry.vertices, &transform)
                         {
-                            callback(DrawPrimitive::Line([p1.xy(), p2.xy()], mesh.color));
+                            callback(DrawPrimitive::Line([p1, p2], color));
                         }
                     }
                 }
 
-                RenderMode::Lines if !mesh.geometry.faces.is_empty() => {
-                    for face in mesh.geometry.faces {
-                        if let Some([p1, p2, p3]) =
-                            self.transform_points(face, mesh.geometry.vertices, transform_matrix)
-                        {
-                            callback(DrawPrimitive::Line([p1.xy(), p2.xy()], mesh.color));
-                            callback(DrawPrimitive::Line([p2.xy(), p3.xy()], mesh.color));
-                            callback(DrawPrimitive::Line([p3.xy(), p1.xy()], mesh.color));
+                RenderMode::Lines if !geometry.faces.is_empty() => {
+                    for face in geometry.faces {
+                        for edge in [[face[0], face[1]], [face[1], face[2]], [face[2], face[0]]] {
+                            if let Some([p1, p2]) =
+                                self.transform_line(&edge, geometry.vertices, &transform)
+                            {
+                                callback(DrawPrimitive::Line([p1, p2], color));
+                            }
                         }
                     }
                 }
 
                 RenderMode::Lines => {}
 
-                RenderMode::SolidLightDir(direction) => {
-                    for (face, normal) in mesh.geometry.faces.iter().zip(mesh.geometry.normals) {
-                        //Backface culling
-                        let normal = Vector3::new(normal[0], normal[1], normal[2]);
+                _ => self.render_faces(mesh, &transform, &mut callback),
+            }
+        }
+    }
 
-                        let transformed_normal = mesh.model_matrix.transform_vector(&normal);
+    /// Ray through the center of a pixel, see `Camera::unproject`
+    pub fn screen_ray(&self, x: i32, y: i32) -> Option<Ray> {
+        self.camera
+            .unproject(x as f32, y as f32, self.width, self.height)
+    }
 
-                        if self.camera.get_direction().dot(&transformed_normal) < 0.0 {
-                            continue;
-                        }
+    /// Closest face under a pixel, culled faces are skipped like when rendering
+    pub fn pick<'a, MS>(&self, x: i32, y: i32, meshes: MS) -> Option<PickHit>
+    where
+        MS: IntoIterator,
+        MS::Item: Borrow<K3dMesh<'a>>,
+    {
+        let ray = self.screen_ray(x, y)?;
+        let mut closest: Option<PickHit> = None;
+
+        for (mesh_index, mesh) in meshes.into_iter().enumerate() {
+            let mesh = mesh.borrow();
+            let geometry = mesh.geometry();
+
+            for (i, face) in geometry.faces.iter().enumerate() {
+                let triangle = face.map(|v| {
+                    mesh.model_matrix
+                        .transform_point(&geometry.vertices[v].into())
+                });
+
+                let normal = (triangle[1] - triangle[0]).cross(&(triangle[2] - triangle[0]));
+
+                if mesh
+                    .face_cull_mode(i)
+                    .culls(ray.direction.dot(&normal) < 0.0)
+                {
+                    continue;
+                }
 
-                        if let Some([p1, p2, p3]) =
-                            self.transform_points(face, mesh.geometry.vertices, transform_matrix)
-                        {
-                            let color_as_float = Vector3::new(
-                                mesh.color.r() as f32 / 32.0,
-                                mesh.color.g() as f32 / 64.0,
-                                mesh.color.b() as f32 / 32.0,
-                            );
-
-                            let mut final_color = Vector3::new(0.0f32, 0.0, 0.0);
-
-                            let intensity = transformed_normal.dot(&direction);
-
-                            let intensity = intensity.max(0.0);
-
-                            final_color += color_as_float * intensity + color_as_float * 0.4;
-
-                            let final_color = Vector3::new(
-                                final_color.x.clamp(0.0, 1.0),
-                                final_color.y.clamp(0.0, 1.0),
-                                final_color.z.clamp(0.0, 1.0),
-                            );
-
-                            let color = Rgb565::new(
-                                (final_color.x * 31.0) as u8,
-                                (final_color.y * 63.0) as u8,
-                                (final_color.z * 31.0) as u8,
-                            );
-                            callback(DrawPrimitive::ColoredTriangle(
-                                [p1.xy(), p2.xy(), p3.xy()],
-                                color,
-                            ));
-                        }
-                    }
+                let Some((distance, barycentric)) = ray.intersect_triangle(&triangle) else {
+                    continue;
+                };
+
+                if closest.is_some_and(|hit| hit.distance <= distance) {
+                    continue;
                 }
 
-                RenderMode::Solid => {
-                    if mesh.geometry.normals.is_empty() {
-                        for face in mesh.geometry.faces.iter() {
-                            if let Some([p1, p2, p3]) = self.transform_points(
-                                face,
-                                mesh.geometry.vertices,
-                                transform_matrix,
-                            ) {
-                                callback(DrawPrimitive::ColoredTriangle(
-                                    [p1.xy(), p2.xy(), p3.xy()],
-                                    mesh.color,
-                                ));
-                            }
-                        }
-                    } else {
-                        for (face, normal) in mesh.geometry.faces.iter().zip(mesh.geometry.normals)
-                        {
-                            //Backface culling
-                            let normal = Vector3::new(normal[0], normal[1], normal[2]);
+                closest = Some(PickHit {
+                    mesh: mesh_index,
+                    face: i,
+                    barycentric,
+                    distance,
+                    point: ray.at(distance),
+                });
+            }
+        }
 
-                            let transformed_normal = mesh.model_matrix.transform_vector(&normal);
+        closest
+    }
 
-                            if self.camera.get_direction().dot(&transformed_normal) < 0.0 {
-                                continue;
-                            }
+    /// Painter's algorithm, for when there is no memory for a depth buffer.
+    ///
+    /// Primitives from all meshes are collected into `buffer`, then emitted back to front.
+    pub fn render_sorted<'a, C, MS, F>(
+        &self,
+        meshes: MS,
+        sort: DepthSort,
+        buffer: &mut [Option<DrawPrimitive<'a, C>>],
+        mut callback: F,
+    ) -> Result<(), SortOverflow>
+    where
+        C: ShadeColor,
+        MS: IntoIterator,
+        MS::Item: Borrow<K3dMesh<'a>>,
+        F: FnMut(DrawPrimitive<'a, C>),
+    {
+        let mut len = 0;
+        let mut required = 0;
 
-                            if let Some([p1, p2, p3]) = self.transform_points(
-                                face,
-                                mesh.geometry.vertices,
-                                transform_matrix,
-                            ) {
-                                callback(DrawPrimitive::ColoredTriangle(
-                                    [p1.xy(), p2.xy(), p3.xy()],
-                                    mesh.color,
-                                ));
-                            }
+        self.render(meshes, |primitive| {
+            if let Some(slot) = buffer.get_mut(len) {
+                *slot = Some(primitive);
+                len += 1;
+            }
+            required += 1;
+        });
+
+        let sorted = &mut buffer[..len];
+        sorted.sort_unstable_by_key(|p| {
+            core::cmp::Reverse(p.as_ref().map_or(i32::MIN, |p| p.depth(sort)))
+        });
+
+        for primitive in sorted.iter_mut().filter_map(Option::take) {
+            callback(primitive);
+        }
+
+        if required > len {
+            return Err(SortOverflow {
+                required,
+                dropped: required - len,
+            });
+        }
+
+        Ok(())
+    }
+
+    fn render_faces<'a, C: ShadeColor, F: FnMut(DrawPrimitive<'a, C>)>(
+        &self,
+        mesh: &K3dMesh<'a>,
+        transform: &MeshTransform,
+        callback: &mut F,
+    ) {
+        let geometry = mesh.geometry();
+        let perspective_correct = mesh.interpolation == Interpolation::PerspectiveCorrect;
+
+        for (i, face) in geometry.faces.iter().enumerate() {
+            let material = mesh.face_material(i);
+
+            // in model space
+            let face_normal = mesh.face_normal(i);
+
+            //Backface culling, done on the screen space winding after clipping
+            let cull = mesh.face_cull_mode(i);
+            let positions = face.map(|v| &geometry.vertices[v]);
+
+            match (&mesh.render_mode, material.texture) {
+                (RenderMode::Textured, Some(texture)) if !geometry.uvs.is_empty() => {
+                    let uvs = face.map(|v| Vector2::from(geometry.uvs[v]));
+
+                    self.transform_attributed_triangle(
+                        positions,
+                        uvs,
+                        transform,
+                        cull,
+                        perspective_correct,
+                        |triangle, uvs, inv_w| {
+                            callback(DrawPrimitive::TexturedTriangle(
+                                triangle,
+                                uvs.map(Into::into),
+                                texture,
+                                inv_w,
+                            ))
+                        },
+                    );
+                }
+
+                (
+                    RenderMode::Gouraud | RenderMode::GouraudLightDir(_) | RenderMode::GouraudLit,
+                    _,
+                ) => {
+                    let colors = face.map(|v| {
+                        let color = geometry.colors.get(v).copied().unwrap_or(material.color);
+
+                        let normal = geometry
+                            .vertex_normals
+                            .get(v)
+                            .map(|n| Vector3::from(*n))
+                            .or(face_normal);
+
+                        #[cfg(feature = "fixed")]
+                        if let Some(color) = self.shade_fixed(
+                            &mesh.render_mode,
+                            material,
+                            color,
+                            normal,
+                            transform.fixed_model.as_ref(),
+                        ) {
+                            return Vector3::from(color.map(Fixed::to_f32));
                         }
-                    }
+
+                        self.shade_model(
+                            mesh,
+                            material,
+                            color,
+                            &geometry.vertices[v].into(),
+                            normal,
+                        )
+                    });
+
+                    self.transform_attributed_triangle(
+                        positions,
+                        colors,
+                        transform,
+                        cull,
+                        perspective_correct,
+                        |triangle, colors, inv_w| {
+                            callback(DrawPrimitive::GouraudTriangle(
+                                triangle,
+                                colors.map(C::from_linear),
+                                inv_w,
+                            ))
+                        },
+                    );
+                }
+
+                // flat shading, also used by textured faces without a texture or uvs
+                _ => {
+                    let color = self.shade_face(mesh, material, face, face_normal, transform);
+
+                    self.transform_triangle(face, geometry.vertices, transform, cull, |triangle| {
+                        callback(DrawPrimitive::ColoredTriangle(triangle, color))
+                    });
                 }
             }
         }
     }
 }
+
+// counter-clockwise in normalized device coordinates, the triangle must be clipped to the near plane
+fn is_front_facing(triangle: &[Vector4<f32>; 3]) -> bool {
+    let [a, b, c] = triangle.map(|p| p.xy() / p.w);
+
+    (b - a).perp(&(c - a)) > 0.0
+}
+
+// the same test without the divide, exact on the fixed point coordinates. With every w positive
+// the sign of the determinant of the x, y, w rows is the sign of the area in ndc
+#[cfg(feature = "fixed")]
+fn is_front_facing_fixed(triangle: &[[Fixed; 4]; 3]) -> bool {
+    let [a, b, c] = triangle.map(|p| [p[0].0 as i128, p[1].0 as i128, p[3].0 as i128]);
+
+    a[0] * (b[1] * c[2] - c[1] * b[2]) - a[1] * (b[0] * c[2] - c[0] * b[2])
+        + a[2] * (b[0] * c[1] - c[0] * b[1])
+        > 0
+}
+
+fn normalize_or_zero(v: Vector3<f32>) -> Vector3<f32> {
+    v.try_normalize(f32::EPSILON).unwrap_or_else(Vector3::zeros)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mesh::Geometry;
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn renders_owned_mesh_every_frame() {
+        let mut engine = K3dengine::new(64, 64);
+        engine.camera.set_position(Point3::new(2.0, 2.0, 3.0));
+        engine.camera.set_target(Point3::new(0.0, 0.0, 0.0));
+
+        let mut mesh = K3dMesh::new(shapes::cube(1.0));
+        mesh.set_render_mode(RenderMode::SolidLightDir(Vector3::new(0.0, 0.0, 1.0)));
+
+        let mut frames = [0; 2];
+        for count in &mut frames {
+            engine.render::<Rgb565, _, _>([&mesh], |_| *count += 1);
+        }
+
+        assert!(frames[0] > 0);
+        assert_eq!(frames[0], frames[1]);
+    }
+
+    #[test]
+    fn average_depth_of_saturated_vertices() {
+        let far = Point3::new(0, 0, i32::MAX);
+        let triangle = DrawPrimitive::ColoredTriangle([far; 3], Rgb565::new(31, 63, 31));
+
+        assert_eq!(triangle.depth(DepthSort::Average), i32::MAX);
+        assert_eq!(triangle.depth(DepthSort::Max), i32::MAX);
+    }
+
+    #[cfg(feature = "fixed")]
+    #[test]
+    fn out_of_range_vertices_use_the_f32_path() {
+        let mut engine = K3dengine::new(320, 240);
+        engine.set_far_clipping(false);
+        engine.camera.set_position(Point3::new(0.0, 0.0, 0.0));
+        engine.camera.set_target(Point3::new(0.0, 0.0, -1.0));
+
+        let model = Matrix4::new_scaling(4.0);
+        let transform = MeshTransform::new(engine.camera.vp_matrix * model, &model);
+
+        // beyond ±32767 after the model matrix, and out of range as an input
+        for vertex in [[5000.0, 2000.0, -10000.0], [4e4, 1e4, -9e4]] {
+            let clip = K3dengine::to_clip_space(&vertex, &transform.clip);
+
+            assert_eq!(
+                engine.transform_point(&vertex, &transform),
+                Some(engine.project(&clip)),
+                "{vertex:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn pick_skips_back_facing_hits() {
+        // a back facing triangle in front of a front facing one, both under the center pixel
+        static GEOMETRY: Geometry = Geometry {
+            vertices: &[
+                [-1.0, -1.0, 2.0],
+                [1.0, -1.0, 2.0],
+                [0.0, 1.0, 2.0],
+                [-1.0, -1.0, 0.0],
+                [1.0, -1.0, 0.0],
+                [0.0, 1.0, 0.0],
+            ],
+            faces: &[[0, 2, 1], [3, 4, 5]],
+            colors: &[],
+            lines: &[],
+            normals: &[],
+            vertex_normals: &[],
+            uvs: &[],
+            material_indices: &[],
+            bounds: None,
+        };
+
+        let mut engine = K3dengine::new(64, 64);
+        engine.camera.set_position(Point3::new(0.0, 0.0, 5.0));
+        engine.camera.set_target(Point3::new(0.0, 0.0, 0.0));
+
+        let mut mesh = K3dMesh::new(GEOMETRY);
+
+        for orthographic in [false, true] {
+            if orthographic {
+                engine.camera.set_orthographic(4.0);
+            }
+
+            mesh.set_cull_mode(CullMode::Back);
+            let hit = engine.pick(32, 32, [&mesh]).unwrap();
+            assert_eq!((hit.mesh, hit.face), (0, 1), "orthographic: {orthographic}");
+            assert!(libm::fabsf(hit.point.z) < 1e-4);
+
+            mesh.set_cull_mode(CullMode::None);
+            let hit = engine.pick(32, 32, [&mesh]).unwrap();
+            assert_eq!(hit.face, 0, "orthographic: {orthographic}");
+        }
+    }
+}
@@ -0,0 +1,268 @@
+use nalgebra::{Point3, Vector3};
+
+#[cfg(feature = "fixed")]
+use crate::fixed::{self, Fixed};
+use crate::material::Material;
+
+pub const MAX_LIGHTS: usize = 8;
+
+/// Point light falloff, the light is scaled by `1 / (constant + linear * d + quadratic * d^2)`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Attenuation {
+    pub constant: f32,
+    pub linear: f32,
+    pub quadratic: f32,
+}
+
+impl Attenuation {
+    pub fn new(constant: f32, linear: f32, quadratic: f32) -> Attenuation {
+        Attenuation {
+            constant,
+            linear,
+            quadratic,
+        }
+    }
+
+    fn factor(&self, distance: f32) -> f32 {
+        1.0 / (self.constant + self.linear * distance + self.quadratic * distance * distance)
+            .max(f32::EPSILON)
+    }
+}
+
+impl Default for Attenuation {
+    fn default() -> Self {
+        Attenuation::new(1.0, 0.0, 1.0)
+    }
+}
+
+/// Light colors are linear rgb, with each channel in 0.0..=1.0
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Light {
+    Directional {
+        // points towards the light, normalized
+        direction: Vector3<f32>,
+        color: Vector3<f32>,
+        intensity: f32,
+    },
+    Point {
+        position: Point3<f32>,
+        color: Vector3<f32>,
+        intensity: f32,
+        attenuation: Attenuation,
+    },
+}
+
+impl Light {
+    pub fn directional(direction: Vector3<f32>, color: Vector3<f32>, intensity: f32) -> Light {
+        Light::Directional {
+            direction: direction.normalize(),
+            color,
+            intensity,
+        }
+    }
+
+    pub fn point(
+        position: Point3<f32>,
+        color: Vector3<f32>,
+        intensity: f32,
+        attenuation: Attenuation,
+    ) -> Light {
+        Light::Point {
+            position,
+            color,
+            intensity,
+            attenuation,
+        }
+    }
+
+    // direction towards the light and the incoming radiance at `position`
+    fn incident(&self, position: &Point3<f32>) -> (Vector3<f32>, Vector3<f32>) {
+        match *self {
+            Light::Directional {
+                direction,
+                color,
+                intensity,
+            } => (direction, color * intensity),
+            Light::Point {
+                position: light_position,
+                color,
+                intensity,
+                attenuation,
+            } => {
+                let to_light = light_position - position;
+                let distance = to_light.norm();
+
+                if distance <= f32::EPSILON {
+                    return (Vector3::zeros(), color * intensity);
+                }
+
+                (
+                    to_light / distance,
+                    color * (intensity * attenuation.factor(distance)),
+                )
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Lighting {
+    pub ambient: Vector3<f32>,
+    lights: [Option<Light>; MAX_LIGHTS],
+}
+
+impl Default for Lighting {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Lighting {
+    pub fn new() -> Lighting {
+        Lighting {
+            ambient: Vector3::new(0.4, 0.4, 0.4),
+            lights: [None; MAX_LIGHTS],
+        }
+    }
+
+    pub fn set_ambient(&mut self, ambient: Vector3<f32>) {
+        self.ambient = ambient;
+    }
+
+    /// Returns the index of the new light, or None if all slots are in use
+    pub fn add_light(&mut self, light: Light) -> Option<usize> {
+        let index = self.lights.iter().position(Option::is_none)?;
+        self.lights[index] = Some(light);
+
+        Some(index)
+    }
+
+    pub fn remove_light(&mut self, index: usize) -> Option<Light> {
+        self.lights.get_mut(index)?.take()
+    }
+
+    pub fn light_mut(&mut self, index: usize) -> Option<&mut Light> {
+        self.lights.get_mut(index)?.as_mut()
+    }
+
+    pub fn clear_lights(&mut self) {
+        self.lights = [None; MAX_LIGHTS];
+    }
+
+    pub fn lights(&self) -> impl Iterator<Item = &Light> {
+        self.lights.iter().flatten()
+    }
+
+    /// Blinn-Phong shading from every light plus ambient, `normal` must be normalized.
+    ///
+    /// `view` points from the surface towards the viewer.
+    pub fn shade(
+        &self,
+        color: Vector3<f32>,
+        material: &Material,
+        position: &Point3<f32>,
+        normal: &Vector3<f32>,
+        view: &Vector3<f32>,
+    ) -> Vector3<f32> {
+        let to_eye = if material.has_specular() {
+            view.try_normalize(f32::EPSILON)
+        } else {
+            None
+        };
+
+        let mut diffuse = self.ambient * material.ambient;
+        let mut specular = Vector3::zeros();
+
+        for (direction, radiance) in self.lights().map(|l| l.incident(position)) {
+            diffuse += radiance * (normal.dot(&direction).max(0.0) * material.diffuse);
+
+            if let Some(to_eye) = &to_eye {
+                specular += radiance * material.specular_factor(normal, &direction, to_eye);
+            }
+        }
+
+        color.component_mul(&diffuse) + material.specular_color.component_mul(&specular)
+    }
+
+    /// Shading for a single white directional light that is not part of the scene
+    pub fn shade_directional(
+        &self,
+        color: Vector3<f32>,
+        material: &Material,
+        normal: &Vector3<f32>,
+        direction: &Vector3<f32>,
+        view: &Vector3<f32>,
+    ) -> Vector3<f32> {
+        let intensity = normal.dot(direction).max(0.0) * material.diffuse;
+        let mut color = color * intensity + color.component_mul(&self.ambient) * material.ambient;
+
+        if material.has_specular() {
+            if let (Some(to_light), Some(to_eye)) = (
+                direction.try_normalize(f32::EPSILON),
+                view.try_normalize(f32::EPSILON),
+            ) {
+                color +=
+                    material.specular_color * material.specular_factor(normal, &to_light, &to_eye);
+            }
+        }
+
+        color
+    }
+
+    /// `shade_directional` in fixed point, without the specular term
+    #[cfg(feature = "fixed")]
+    pub fn shade_directional_fixed(
+        &self,
+        color: [Fixed; 3],
+        material: &Material,
+        normal: &[Fixed; 3],
+        direction: &[Fixed; 3],
+    ) -> [Fixed; 3] {
+        let intensity =
+            fixed::dot(normal, direction).max(Fixed::ZERO) * Fixed::from_f32(material.diffuse);
+        let ambient = Fixed::from_f32(material.ambient);
+
+        [0, 1, 2]
+            .map(|i| color[i] * intensity + color[i] * Fixed::from_f32(self.ambient[i]) * ambient)
+    }
+}
+
+#[cfg(all(test, feature = "fixed"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixed_directional_shading_matches_f32() {
+        let mut lighting = Lighting::new();
+        lighting.set_ambient(Vector3::new(0.3, 0.4, 0.2));
+
+        let material = Material {
+            ambient: 0.8,
+            diffuse: 0.9,
+            ..Material::default()
+        };
+
+        let direction = Vector3::new(0.3, 1.0, 0.5).normalize();
+        let to_fixed = |v: &Vector3<f32>| [v.x, v.y, v.z].map(Fixed::from_f32);
+
+        for i in 0..200 {
+            let t = i as f32 * 0.1;
+            let normal = Vector3::new(libm::sinf(t), libm::cosf(t * 1.7), libm::sinf(t * 0.3));
+            let normal = normal.normalize();
+            let color = Vector3::new((t * 0.37) % 1.0, (t * 0.61) % 1.0, (t * 0.13) % 1.0);
+
+            let expected =
+                lighting.shade_directional(color, &material, &normal, &direction, &Vector3::z());
+            let result = lighting.shade_directional_fixed(
+                to_fixed(&color),
+                &material,
+                &to_fixed(&normal),
+                &to_fixed(&direction),
+            );
+
+            for c in 0..3 {
+                assert!((result[c].to_f32() - expected[c]).abs() < 1e-4);
+            }
+        }
+    }
+}
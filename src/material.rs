@@ -0,0 +1,67 @@
+use embedded_graphics_core::pixelcolor::{Rgb565, WebColors};
+use nalgebra::Vector3;
+
+use crate::texture::Texture;
+
+/// Surface appearance of a mesh, or of the faces that reference it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Material<'a> {
+    pub color: Rgb565,
+    pub ambient: f32,
+    pub diffuse: f32,
+    pub specular: f32,
+    // linear rgb, with each channel in 0.0..=1.0
+    pub specular_color: Vector3<f32>,
+    pub shininess: f32,
+    // the base color is added on top of the lighting, so the surface glows in the dark
+    pub emissive: bool,
+    pub texture: Option<Texture<'a>>,
+    // disables backface culling
+    pub double_sided: bool,
+    // ignores lighting even in the lit render modes
+    pub unlit: bool,
+}
+
+impl Default for Material<'_> {
+    fn default() -> Self {
+        Material::new(Rgb565::CSS_WHITE)
+    }
+}
+
+impl<'a> Material<'a> {
+    pub const fn new(color: Rgb565) -> Material<'a> {
+        Material {
+            color,
+            ambient: 1.0,
+            diffuse: 1.0,
+            specular: 1.0,
+            specular_color: Vector3::new(0.0, 0.0, 0.0),
+            shininess: 32.0,
+            emissive: false,
+            texture: None,
+            double_sided: false,
+            unlit: false,
+        }
+    }
+
+    pub fn has_specular(&self) -> bool {
+        self.specular > 0.0 && self.specular_color != Vector3::zeros()
+    }
+
+    // Blinn-Phong term, all vectors must be normalized
+    pub(crate) fn specular_factor(
+        &self,
+        normal: &Vector3<f32>,
+        to_light: &Vector3<f32>,
+        to_eye: &Vector3<f32>,
+    ) -> f32 {
+        if normal.dot(to_light) <= 0.0 {
+            return 0.0;
+        }
+
+        match (to_light + to_eye).try_normalize(f32::EPSILON) {
+            Some(half) => libm::powf(normal.dot(&half).max(0.0), self.shininess) * self.specular,
+            None => 0.0,
+        }
+    }
+}
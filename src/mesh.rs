@@ -1,21 +1,65 @@
-use embedded_graphics_core::pixelcolor::{Rgb565, WebColors};
+#[cfg(feature = "alloc")]
+use alloc::{vec, vec::Vec};
+
+use embedded_graphics_core::pixelcolor::Rgb565;
 use log::error;
 use nalgebra::{Point3, Similarity3, UnitQuaternion, Vector3};
 
+use crate::bounds::Bounds;
+use crate::material::Material;
+use crate::normals;
+use crate::texture::Texture;
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum RenderMode {
     Points,
     Lines,
     Solid,
     SolidLightDir(Vector3<f32>),
+    SolidLit,
+    Gouraud,
+    GouraudLightDir(Vector3<f32>),
+    GouraudLit,
+    Textured,
+}
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum Interpolation {
+    #[default]
+    Affine,
+    PerspectiveCorrect,
 }
-#[derive(Debug, Default)]
+
+/// Which side of the faces is discarded, decided by the winding on screen
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum CullMode {
+    #[default]
+    Back,
+    Front,
+    None,
+}
+
+impl CullMode {
+    pub fn culls(&self, front_facing: bool) -> bool {
+        match self {
+            CullMode::Back => !front_facing,
+            CullMode::Front => front_facing,
+            CullMode::None => false,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
 pub struct Geometry<'a> {
     pub vertices: &'a [[f32; 3]],
     pub faces: &'a [[usize; 3]],
     pub colors: &'a [Rgb565],
     pub lines: &'a [[usize; 2]],
     pub normals: &'a [[f32; 3]],
+    pub vertex_normals: &'a [[f32; 3]],
+    pub uvs: &'a [[f32; 2]],
+    pub material_indices: &'a [usize],
+    // computed from the vertices by `K3dMesh::new` when missing
+    pub bounds: Option<Bounds>,
 }
 
 impl Geometry<'_> {
@@ -47,9 +91,25 @@ impl Geometry<'_> {
             return false;
         }
 
+        if !self.vertex_normals.is_empty() && self.vertex_normals.len() != self.vertices.len() {
+            error!("Vertex normals are not the same length as vertices");
+            return false;
+        }
+
+        if !self.uvs.is_empty() && self.uvs.len() != self.vertices.len() {
+            error!("UVs are not the same length as vertices");
+            return false;
+        }
+
+        if !self.material_indices.is_empty() && self.material_indices.len() != self.faces.len() {
+            error!("Material indices are not the same length as faces");
+            return false;
+        }
+
         true
     }
 
+    #[cfg(feature = "alloc")]
     pub fn lines_from_faces(faces: &[[usize; 3]]) -> Vec<(usize, usize)> {
         let mut lines = Vec::new();
         for face in faces {
@@ -69,36 +129,242 @@ impl Geometry<'_> {
     }
 }
 
+/// Heap allocated counterpart of `Geometry`, for meshes generated or loaded at runtime
+#[cfg(feature = "alloc")]
+#[derive(Debug, Default, Clone)]
+pub struct OwnedGeometry {
+    pub vertices: Vec<[f32; 3]>,
+    pub faces: Vec<[usize; 3]>,
+    pub colors: Vec<Rgb565>,
+    pub lines: Vec<[usize; 2]>,
+    pub normals: Vec<[f32; 3]>,
+    pub vertex_normals: Vec<[f32; 3]>,
+    pub uvs: Vec<[f32; 2]>,
+    pub material_indices: Vec<usize>,
+    pub bounds: Option<Bounds>,
+}
+
+#[cfg(feature = "alloc")]
+impl OwnedGeometry {
+    pub fn as_geometry(&self) -> Geometry<'_> {
+        Geometry {
+            vertices: &self.vertices,
+            faces: &self.faces,
+            colors: &self.colors,
+            lines: &self.lines,
+            normals: &self.normals,
+            vertex_normals: &self.vertex_normals,
+            uvs: &self.uvs,
+            material_indices: &self.material_indices,
+            bounds: self.bounds,
+        }
+    }
+
+    /// Fills `lines` with the unique edges of the faces, for wireframe rendering
+    pub fn generate_lines(&mut self) {
+        self.lines = Geometry::lines_from_faces(&self.faces)
+            .into_iter()
+            .map(|(a, b)| [a, b])
+            .collect();
+    }
+
+    /// Angle weighted smooth normals, faces meeting at more than `crease_angle` radians keep a
+    /// hard edge, which splits the vertices along it
+    pub fn generate_vertex_normals(&mut self, crease_angle: Option<f32>) {
+        match crease_angle {
+            Some(crease_angle) => normals::crease_vertex_normals(self, crease_angle),
+            None => {
+                self.vertex_normals = vec![[0.0; 3]; self.vertices.len()];
+                normals::smooth_vertex_normals(
+                    &self.vertices,
+                    &self.faces,
+                    &mut self.vertex_normals,
+                );
+            }
+        }
+    }
+
+    /// Replaces the face normals with the ones of the vertex winding
+    pub fn recompute_normals(&mut self) {
+        self.normals = self
+            .faces
+            .iter()
+            .map(|face| normals::face_normal(&self.vertices, face).into())
+            .collect();
+    }
+
+    /// Recomputes the bounding volumes, needed after editing the vertices of a mesh
+    pub fn update_bounds(&mut self) {
+        self.bounds = Bounds::from_vertices(&self.vertices);
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl From<Geometry<'_>> for OwnedGeometry {
+    fn from(geometry: Geometry<'_>) -> Self {
+        OwnedGeometry {
+            vertices: geometry.vertices.to_vec(),
+            faces: geometry.faces.to_vec(),
+            colors: geometry.colors.to_vec(),
+            lines: geometry.lines.to_vec(),
+            normals: geometry.normals.to_vec(),
+            vertex_normals: geometry.vertex_normals.to_vec(),
+            uvs: geometry.uvs.to_vec(),
+            material_indices: geometry.material_indices.to_vec(),
+            bounds: geometry.bounds,
+        }
+    }
+}
+
+/// Geometry of a mesh, either borrowed or owned by it
+#[derive(Debug, Clone)]
+pub enum MeshGeometry<'a> {
+    Borrowed(Geometry<'a>),
+    #[cfg(feature = "alloc")]
+    Owned(OwnedGeometry),
+}
+
+impl MeshGeometry<'_> {
+    pub fn as_geometry(&self) -> Geometry<'_> {
+        match self {
+            MeshGeometry::Borrowed(geometry) => *geometry,
+            #[cfg(feature = "alloc")]
+            MeshGeometry::Owned(geometry) => geometry.as_geometry(),
+        }
+    }
+
+    fn bounds_mut(&mut self) -> &mut Option<Bounds> {
+        match self {
+            MeshGeometry::Borrowed(geometry) => &mut geometry.bounds,
+            #[cfg(feature = "alloc")]
+            MeshGeometry::Owned(geometry) => &mut geometry.bounds,
+        }
+    }
+}
+
+impl<'a> From<Geometry<'a>> for MeshGeometry<'a> {
+    fn from(geometry: Geometry<'a>) -> Self {
+        MeshGeometry::Borrowed(geometry)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl From<OwnedGeometry> for MeshGeometry<'_> {
+    fn from(geometry: OwnedGeometry) -> Self {
+        MeshGeometry::Owned(geometry)
+    }
+}
+
 pub struct K3dMesh<'a> {
     pub similarity: Similarity3<f32>,
     pub model_matrix: nalgebra::Matrix4<f32>,
 
-    pub color: Rgb565,
+    pub material: Material<'a>,
+    // indexed by `Geometry::material_indices`
+    pub materials: &'a [Material<'a>],
     pub render_mode: RenderMode,
-    pub geometry: Geometry<'a>,
+    pub interpolation: Interpolation,
+    pub cull_mode: CullMode,
+    // derive the face normals from the winding instead of trusting `Geometry::normals`
+    pub recompute_normals: bool,
+    pub geometry: MeshGeometry<'a>,
 }
 
-impl K3dMesh<'_> {
-    pub fn new(geometry: Geometry) -> K3dMesh {
-        debug_assert!(geometry.check_validity());
+impl<'a> K3dMesh<'a> {
+    pub fn new(geometry: impl Into<MeshGeometry<'a>>) -> K3dMesh<'a> {
+        let mut geometry = geometry.into();
+        debug_assert!(geometry.as_geometry().check_validity());
+
+        if geometry.as_geometry().bounds.is_none() {
+            *geometry.bounds_mut() = Bounds::from_vertices(geometry.as_geometry().vertices);
+        }
+
         let sim = Similarity3::new(Vector3::new(0.0, 0.0, 0.0), nalgebra::zero(), 1.0);
         K3dMesh {
             model_matrix: sim.to_homogeneous(),
             similarity: sim,
-            color: Rgb565::CSS_WHITE,
+            material: Material::default(),
+            materials: &[],
             render_mode: RenderMode::Points,
+            interpolation: Interpolation::Affine,
+            cull_mode: CullMode::Back,
+            recompute_normals: false,
             geometry,
         }
     }
 
     pub fn set_color(&mut self, color: Rgb565) {
-        self.color = color;
+        self.material.color = color;
+    }
+
+    pub fn set_texture(&mut self, texture: Texture<'a>) {
+        self.material.texture = Some(texture);
+    }
+
+    pub fn set_material(&mut self, material: Material<'a>) {
+        self.material = material;
+    }
+
+    pub fn set_materials(&mut self, materials: &'a [Material<'a>]) {
+        self.materials = materials;
+    }
+
+    pub fn geometry(&self) -> Geometry<'_> {
+        self.geometry.as_geometry()
+    }
+
+    pub fn face_material(&self, face: usize) -> &Material<'a> {
+        self.geometry
+            .as_geometry()
+            .material_indices
+            .get(face)
+            .and_then(|&i| self.materials.get(i))
+            .unwrap_or(&self.material)
+    }
+
+    pub fn set_cull_mode(&mut self, cull_mode: CullMode) {
+        self.cull_mode = cull_mode;
+    }
+
+    /// Cull mode of a face, double sided materials are never culled
+    pub fn face_cull_mode(&self, face: usize) -> CullMode {
+        if self.face_material(face).double_sided {
+            CullMode::None
+        } else {
+            self.cull_mode
+        }
+    }
+
+    pub fn set_recompute_normals(&mut self, recompute: bool) {
+        self.recompute_normals = recompute;
+    }
+
+    /// Normal of a face in model space, None if the geometry has no normals.
+    ///
+    /// Zero normals, common in stl files, are replaced by the normal of the winding.
+    pub fn face_normal(&self, face: usize) -> Option<Vector3<f32>> {
+        let geometry = self.geometry();
+
+        let stored = geometry.normals.get(face).map(|n| Vector3::from(*n));
+
+        if self.recompute_normals || stored.is_some_and(|n| n.norm_squared() <= f32::EPSILON) {
+            Some(normals::face_normal(
+                geometry.vertices,
+                &geometry.faces[face],
+            ))
+        } else {
+            stored
+        }
     }
 
     pub fn set_render_mode(&mut self, mode: RenderMode) {
         self.render_mode = mode;
     }
 
+    pub fn set_interpolation(&mut self, interpolation: Interpolation) {
+        self.interpolation = interpolation;
+    }
+
     pub fn set_position(&mut self, x: f32, y: f32, z: f32) {
         self.similarity.isometry.translation.x = x;
         self.similarity.isometry.translation.y = y;
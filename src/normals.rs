@@ -0,0 +1,231 @@
+//! Vertex normal generation for smooth shading
+
+#[cfg(feature = "alloc")]
+use alloc::{vec, vec::Vec};
+
+use nalgebra::Vector3;
+
+#[cfg(feature = "alloc")]
+use crate::mesh::OwnedGeometry;
+
+/// Normalized normal of a counter-clockwise face, zero for degenerate faces
+pub fn face_normal(vertices: &[[f32; 3]], face: &[usize; 3]) -> Vector3<f32> {
+    let [a, b, c] = face.map(|v| Vector3::from(vertices[v]));
+
+    (b - a)
+        .cross(&(c - a))
+        .try_normalize(f32::EPSILON)
+        .unwrap_or_else(Vector3::zeros)
+}
+
+// interior angle of each corner, used to weight the face normals
+fn corner_angles(vertices: &[[f32; 3]], face: &[usize; 3]) -> [f32; 3] {
+    let p = face.map(|v| Vector3::from(vertices[v]));
+
+    [0, 1, 2].map(|i| {
+        let e1 = p[(i + 1) % 3] - p[i];
+        let e2 = p[(i + 2) % 3] - p[i];
+
+        match (
+            e1.try_normalize(f32::EPSILON),
+            e2.try_normalize(f32::EPSILON),
+        ) {
+            (Some(e1), Some(e2)) => libm::acosf(e1.dot(&e2).clamp(-1.0, 1.0)),
+            _ => 0.0,
+        }
+    })
+}
+
+/// Angle weighted vertex normals, every face sharing a vertex is smoothed across.
+///
+/// `out` must have the same length as `vertices`, vertices without faces get a zero normal.
+pub fn smooth_vertex_normals(vertices: &[[f32; 3]], faces: &[[usize; 3]], out: &mut [[f32; 3]]) {
+    assert!(
+        out.len() == vertices.len(),
+        "Output does not match vertices"
+    );
+
+    for normal in out.iter_mut() {
+        *normal = [0.0; 3];
+    }
+
+    for face in faces {
+        let normal = face_normal(vertices, face);
+        let angles = corner_angles(vertices, face);
+
+        for (&v, angle) in face.iter().zip(angles) {
+            out[v] = (Vector3::from(out[v]) + normal * angle).into();
+        }
+    }
+
+    for normal in out.iter_mut() {
+        *normal = Vector3::from(*normal)
+            .try_normalize(f32::EPSILON)
+            .unwrap_or_else(Vector3::zeros)
+            .into();
+    }
+}
+
+/// Smooth vertex normals that keep hard edges between faces meeting at more than
+/// `crease_angle` radians.
+///
+/// Vertices on a hard edge are split, the copies are appended so existing indices stay valid.
+#[cfg(feature = "alloc")]
+pub fn crease_vertex_normals(geometry: &mut OwnedGeometry, crease_angle: f32) {
+    let vertex_count = geometry.vertices.len();
+    let cos_crease = libm::cosf(crease_angle);
+
+    let face_normals: Vec<_> = geometry
+        .faces
+        .iter()
+        .map(|face| face_normal(&geometry.vertices, face))
+        .collect();
+    let angles: Vec<_> = geometry
+        .faces
+        .iter()
+        .map(|face| corner_angles(&geometry.vertices, face))
+        .collect();
+
+    // faces around each vertex, with the corner the vertex is at
+    let mut adjacent: Vec<Vec<(usize, usize)>> = vec![Vec::new(); vertex_count];
+    for (f, face) in geometry.faces.iter().enumerate() {
+        for (corner, &v) in face.iter().enumerate() {
+            adjacent[v].push((f, corner));
+        }
+    }
+
+    // the vertex each new vertex was copied from
+    let mut source: Vec<usize> = (0..vertex_count).collect();
+    let mut normals: Vec<Option<Vector3<f32>>> = vec![None; vertex_count];
+    // copies made of each original vertex
+    let mut copies: Vec<Vec<usize>> = vec![Vec::new(); vertex_count];
+
+    for f in 0..geometry.faces.len() {
+        for corner in 0..3 {
+            let v = geometry.faces[f][corner];
+
+            let normal = adjacent[v]
+                .iter()
+                .filter(|&&(g, _)| face_normals[f].dot(&face_normals[g]) >= cos_crease)
+                .map(|&(g, c)| face_normals[g] * angles[g][c])
+                .sum::<Vector3<f32>>()
+                .try_normalize(f32::EPSILON)
+                .unwrap_or(face_normals[f]);
+
+            let same = |n: &Option<Vector3<f32>>| n.is_some_and(|n| n.dot(&normal) > 0.9999);
+
+            let index = if normals[v].is_none() || same(&normals[v]) {
+                normals[v] = Some(normal);
+                v
+            } else if let Some(&copy) = copies[v].iter().find(|&&c| same(&normals[c])) {
+                copy
+            } else {
+                source.push(v);
+                normals.push(Some(normal));
+                copies[v].push(normals.len() - 1);
+                normals.len() - 1
+            };
+
+            geometry.faces[f][corner] = index;
+        }
+    }
+
+    append_copies(&mut geometry.vertices, &source, vertex_count);
+    append_copies(&mut geometry.uvs, &source, vertex_count);
+    append_copies(&mut geometry.colors, &source, vertex_count);
+
+    geometry.vertex_normals = normals
+        .into_iter()
+        .map(|n| n.unwrap_or_else(Vector3::zeros).into())
+        .collect();
+}
+
+// duplicates the per-vertex attributes of the split vertices, if there are any
+#[cfg(feature = "alloc")]
+fn append_copies<T: Copy>(attributes: &mut Vec<T>, source: &[usize], vertex_count: usize) {
+    if attributes.len() == vertex_count {
+        for &v in &source[vertex_count..] {
+            attributes.push(attributes[v]);
+        }
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use core::f32::consts::PI;
+
+    use super::*;
+    use crate::shapes::CUBE;
+
+    // the shared corner cube, with uvs taken from the positions to check that copies follow
+    fn cube() -> OwnedGeometry {
+        let mut geometry = OwnedGeometry::from(CUBE);
+        geometry.uvs = geometry.vertices.iter().map(|v| [v[0], v[1]]).collect();
+
+        geometry
+    }
+
+    fn corners(geometry: &OwnedGeometry) -> Vec<[f32; 3]> {
+        geometry
+            .faces
+            .iter()
+            .flatten()
+            .map(|&v| geometry.vertices[v])
+            .collect()
+    }
+
+    fn assert_valid(geometry: &OwnedGeometry) {
+        let count = geometry.vertices.len();
+
+        assert_eq!(geometry.vertex_normals.len(), count);
+        assert_eq!(geometry.uvs.len(), count);
+        assert!(geometry.colors.is_empty());
+        assert!(geometry.faces.iter().flatten().all(|&v| v < count));
+        assert!(geometry.lines.iter().flatten().all(|&v| v < count));
+
+        for (vertex, uv) in geometry.vertices.iter().zip(&geometry.uvs) {
+            assert_eq!(*uv, [vertex[0], vertex[1]]);
+        }
+    }
+
+    #[test]
+    fn hard_edges_split_the_cube_into_flat_sides() {
+        let mut geometry = cube();
+        let before = corners(&geometry);
+
+        crease_vertex_normals(&mut geometry, 30f32.to_radians());
+
+        assert_eq!(geometry.vertices.len(), 24);
+        assert_valid(&geometry);
+        assert_eq!(corners(&geometry), before);
+        assert_eq!(geometry.lines, CUBE.lines);
+
+        for face in &geometry.faces {
+            let normal = face_normal(&geometry.vertices, face);
+
+            for &v in face {
+                let vertex_normal = Vector3::from(geometry.vertex_normals[v]);
+                assert!((vertex_normal - normal).norm() < 1e-5, "{vertex_normal:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn crease_of_pi_keeps_the_corners_shared() {
+        let mut geometry = cube();
+
+        crease_vertex_normals(&mut geometry, PI);
+
+        assert_eq!(geometry.vertices.len(), 8);
+        assert_valid(&geometry);
+        assert_eq!(geometry.faces, CUBE.faces);
+
+        for (vertex, normal) in geometry.vertices.iter().zip(&geometry.vertex_normals) {
+            let expected = Vector3::from(*vertex).normalize();
+            assert!(
+                (Vector3::from(*normal) - expected).norm() < 1e-5,
+                "{normal:?}"
+            );
+        }
+    }
+}
@@ -0,0 +1,322 @@
+use core::f32::consts;
+
+use nalgebra::{Point3, UnitQuaternion, Vector3};
+
+use crate::camera::{align_up, Camera};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OrbitMode {
+    /// Azimuth around the camera's up vector and clamped elevation, the horizon always stays level
+    #[default]
+    Turntable,
+    /// Free rotation around the camera's own axes, the camera can roll and go upside down
+    Arcball,
+}
+
+/// Orbits a camera around a target point, driven by 2D input deltas.
+///
+/// Input only moves the goal values, `update` eases the camera towards them and leaves it untouched
+/// once it has settled.
+#[derive(Debug, Clone)]
+pub struct OrbitController {
+    target: Point3<f32>,
+    mode: OrbitMode,
+
+    azimuth: f32,
+    elevation: f32,
+    distance: f32,
+    rotation: UnitQuaternion<f32>,
+
+    goal_azimuth: f32,
+    goal_elevation: f32,
+    goal_distance: f32,
+    goal_rotation: UnitQuaternion<f32>,
+
+    min_elevation: f32,
+    max_elevation: f32,
+    min_distance: f32,
+    max_distance: f32,
+
+    // radians per unit of input
+    sensitivity: f32,
+    // fraction of the remaining motion left after each update, 0.0 disables damping
+    damping: f32,
+    dirty: bool,
+}
+
+impl OrbitController {
+    pub fn new(target: Point3<f32>, distance: f32) -> OrbitController {
+        let limit = consts::FRAC_PI_2 - 0.01;
+
+        OrbitController {
+            target,
+            mode: OrbitMode::Turntable,
+            azimuth: 0.0,
+            elevation: 0.0,
+            distance,
+            rotation: UnitQuaternion::identity(),
+            goal_azimuth: 0.0,
+            goal_elevation: 0.0,
+            goal_distance: distance,
+            goal_rotation: UnitQuaternion::identity(),
+            min_elevation: -limit,
+            max_elevation: limit,
+            min_distance: 0.0,
+            max_distance: f32::INFINITY,
+            sensitivity: 0.01,
+            damping: 0.0,
+            dirty: true,
+        }
+    }
+
+    pub fn set_mode(&mut self, mode: OrbitMode) {
+        if mode == self.mode {
+            return;
+        }
+
+        match mode {
+            OrbitMode::Arcball => {
+                self.rotation = turntable_rotation(self.azimuth, self.elevation);
+                self.goal_rotation = turntable_rotation(self.goal_azimuth, self.goal_elevation);
+            }
+            OrbitMode::Turntable => {
+                (self.azimuth, self.elevation) = spherical_angles(&self.rotation);
+                (self.goal_azimuth, self.goal_elevation) = spherical_angles(&self.goal_rotation);
+                self.elevation = self.clamp_elevation(self.elevation);
+                self.goal_elevation = self.clamp_elevation(self.goal_elevation);
+            }
+        }
+
+        self.mode = mode;
+        self.dirty = true;
+    }
+
+    pub fn get_mode(&self) -> OrbitMode {
+        self.mode
+    }
+
+    pub fn set_target(&mut self, target: Point3<f32>) {
+        self.target = target;
+        self.dirty = true;
+    }
+
+    pub fn get_target(&self) -> Point3<f32> {
+        self.target
+    }
+
+    /// Elevation limits in radians, only used in turntable mode
+    pub fn set_pitch_limits(&mut self, min: f32, max: f32) {
+        self.min_elevation = min.max(-consts::FRAC_PI_2);
+        self.max_elevation = max.min(consts::FRAC_PI_2).max(self.min_elevation);
+        self.goal_elevation = self.clamp_elevation(self.goal_elevation);
+    }
+
+    pub fn set_zoom_limits(&mut self, min: f32, max: f32) {
+        self.min_distance = min.max(0.0);
+        self.max_distance = max.max(self.min_distance);
+        self.goal_distance = self.clamp_distance(self.goal_distance);
+    }
+
+    pub fn set_sensitivity(&mut self, sensitivity: f32) {
+        self.sensitivity = sensitivity;
+    }
+
+    /// 0.0 follows the input immediately, values closer to 1.0 ease in more slowly
+    pub fn set_damping(&mut self, damping: f32) {
+        self.damping = damping.clamp(0.0, 0.999);
+    }
+
+    pub fn set_azimuth(&mut self, azimuth: f32) {
+        self.goal_azimuth = azimuth;
+        self.sync_goal_rotation();
+    }
+
+    pub fn set_elevation(&mut self, elevation: f32) {
+        self.goal_elevation = self.clamp_elevation(elevation);
+        self.sync_goal_rotation();
+    }
+
+    pub fn set_distance(&mut self, distance: f32) {
+        self.goal_distance = self.clamp_distance(distance);
+    }
+
+    pub fn get_azimuth(&self) -> f32 {
+        match self.mode {
+            OrbitMode::Turntable => self.azimuth,
+            OrbitMode::Arcball => spherical_angles(&self.rotation).0,
+        }
+    }
+
+    pub fn get_elevation(&self) -> f32 {
+        match self.mode {
+            OrbitMode::Turntable => self.elevation,
+            OrbitMode::Arcball => spherical_angles(&self.rotation).1,
+        }
+    }
+
+    pub fn get_distance(&self) -> f32 {
+        self.distance
+    }
+
+    /// Positive `dx` orbits the camera to the right, positive `dy` orbits it upwards
+    pub fn rotate(&mut self, dx: f32, dy: f32) {
+        let yaw = dx * self.sensitivity;
+        let pitch = dy * self.sensitivity;
+
+        match self.mode {
+            OrbitMode::Turntable => {
+                self.goal_azimuth += yaw;
+                self.goal_elevation = self.clamp_elevation(self.goal_elevation + pitch);
+            }
+            OrbitMode::Arcball => {
+                self.goal_rotation = self.goal_rotation
+                    * UnitQuaternion::from_axis_angle(&Vector3::y_axis(), yaw)
+                    * UnitQuaternion::from_axis_angle(&Vector3::x_axis(), -pitch);
+                self.goal_rotation.renormalize_fast();
+            }
+        }
+    }
+
+    /// Scales the distance, values above 1.0 move away from the target
+    pub fn zoom(&mut self, factor: f32) {
+        self.goal_distance = self.clamp_distance(self.goal_distance * factor);
+    }
+
+    /// Steps the damping and moves the camera, returns false if the camera did not change
+    pub fn update(&mut self, camera: &mut Camera) -> bool {
+        let t = 1.0 - self.damping;
+        let mut moved = self.dirty;
+
+        moved |= approach(&mut self.distance, self.goal_distance, t);
+
+        match self.mode {
+            OrbitMode::Turntable => {
+                moved |= approach(&mut self.azimuth, self.goal_azimuth, t);
+                moved |= approach(&mut self.elevation, self.goal_elevation, t);
+            }
+            OrbitMode::Arcball => {
+                if self.rotation.angle_to(&self.goal_rotation) > 1e-4 {
+                    self.rotation = self.rotation.slerp(&self.goal_rotation, t);
+                    moved = true;
+                } else if self.rotation != self.goal_rotation {
+                    self.rotation = self.goal_rotation;
+                    moved = true;
+                }
+            }
+        }
+
+        if !moved {
+            return false;
+        }
+
+        self.apply(camera);
+        self.dirty = false;
+
+        true
+    }
+
+    /// Jumps straight to the goal values, skipping the damping
+    pub fn snap(&mut self, camera: &mut Camera) {
+        self.azimuth = self.goal_azimuth;
+        self.elevation = self.goal_elevation;
+        self.distance = self.goal_distance;
+        self.rotation = self.goal_rotation;
+
+        self.apply(camera);
+        self.dirty = false;
+    }
+
+    // the angles and the rotation are relative to the camera's up vector, which is left as it is
+    fn apply(&self, camera: &mut Camera) {
+        let align = align_up(&camera.get_up());
+
+        match self.mode {
+            OrbitMode::Turntable => {
+                let (sin_az, cos_az) = libm::sincosf(self.azimuth);
+                let (sin_el, cos_el) = libm::sincosf(self.elevation);
+                let offset = align * Vector3::new(cos_el * sin_az, sin_el, cos_el * cos_az);
+
+                camera.look_at(self.target + offset * self.distance, self.target);
+            }
+            OrbitMode::Arcball => {
+                let rotation = align * self.rotation;
+                let offset = rotation * Vector3::z();
+
+                camera.set_pose(self.target + offset * self.distance, rotation);
+            }
+        }
+    }
+
+    fn sync_goal_rotation(&mut self) {
+        if self.mode == OrbitMode::Arcball {
+            self.goal_rotation = turntable_rotation(self.goal_azimuth, self.goal_elevation);
+        }
+    }
+
+    fn clamp_elevation(&self, elevation: f32) -> f32 {
+        elevation.clamp(self.min_elevation, self.max_elevation)
+    }
+
+    fn clamp_distance(&self, distance: f32) -> f32 {
+        distance.clamp(self.min_distance, self.max_distance)
+    }
+}
+
+// camera orientation looking at the target from the given angles
+fn turntable_rotation(azimuth: f32, elevation: f32) -> UnitQuaternion<f32> {
+    UnitQuaternion::from_axis_angle(&Vector3::y_axis(), azimuth)
+        * UnitQuaternion::from_axis_angle(&Vector3::x_axis(), -elevation)
+}
+
+fn spherical_angles(rotation: &UnitQuaternion<f32>) -> (f32, f32) {
+    let offset = rotation * Vector3::z();
+
+    (
+        libm::atan2f(offset.x, offset.z),
+        libm::asinf(offset.y.clamp(-1.0, 1.0)),
+    )
+}
+
+// moves `value` a fraction `t` of the way to `goal`, snapping once it is close enough
+fn approach(value: &mut f32, goal: f32, t: f32) -> bool {
+    if *value == goal {
+        return false;
+    }
+
+    if libm::fabsf(goal - *value) < 1e-4 {
+        *value = goal;
+    } else {
+        *value += (goal - *value) * t;
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn turntable_orbits_around_the_camera_up_vector() {
+        let mut camera = Camera::new(1.0);
+        camera.set_up(Vector3::z());
+
+        let target = Point3::new(1.0, 2.0, 3.0);
+        let mut orbit = OrbitController::new(target, 5.0);
+
+        for elevation in [0.0, 0.5] {
+            orbit.set_elevation(elevation);
+            orbit.set_azimuth(1.0 + elevation);
+            orbit.update(&mut camera);
+
+            let offset = camera.position - target;
+            assert_eq!(camera.get_up(), Vector3::z());
+            assert!(libm::fabsf(offset.norm() - 5.0) < 1e-4);
+            assert!(
+                libm::fabsf(offset.z - 5.0 * libm::sinf(elevation)) < 1e-4,
+                "{offset:?}"
+            );
+            assert!((camera.forward() - -offset.normalize()).norm() < 1e-4);
+        }
+    }
+}
@@ -1,3 +1,6 @@
+use std::format;
+use std::string::String;
+
 #[derive(Debug)]
 pub struct PerformanceCounter {
     start_of_frame: std::time::Instant,
@@ -0,0 +1,70 @@
+use nalgebra::{Point3, Vector3};
+
+/// Half-line in world space, `direction` is normalized
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ray {
+    pub origin: Point3<f32>,
+    pub direction: Vector3<f32>,
+}
+
+/// Closest triangle hit by a picking ray
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PickHit {
+    // index of the mesh in the iterator passed to `pick`
+    pub mesh: usize,
+    pub face: usize,
+    // weights of the face's three vertices at the hit point
+    pub barycentric: [f32; 3],
+    // along the ray, in world units
+    pub distance: f32,
+    pub point: Point3<f32>,
+}
+
+impl Ray {
+    pub fn new(origin: Point3<f32>, direction: Vector3<f32>) -> Ray {
+        Ray {
+            origin,
+            direction: direction.normalize(),
+        }
+    }
+
+    pub fn at(&self, distance: f32) -> Point3<f32> {
+        self.origin + self.direction * distance
+    }
+
+    /// Möller–Trumbore intersection, returns the distance and the barycentric weights.
+    ///
+    /// Both sides of the triangle are hit, culling is left to the caller.
+    pub fn intersect_triangle(&self, triangle: &[Point3<f32>; 3]) -> Option<(f32, [f32; 3])> {
+        let edge1 = triangle[1] - triangle[0];
+        let edge2 = triangle[2] - triangle[0];
+
+        let p = self.direction.cross(&edge2);
+        let det = edge1.dot(&p);
+
+        if libm::fabsf(det) < f32::EPSILON {
+            return None;
+        }
+
+        let inv_det = 1.0 / det;
+        let s = self.origin - triangle[0];
+
+        let u = s.dot(&p) * inv_det;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+
+        let q = s.cross(&edge1);
+        let v = self.direction.dot(&q) * inv_det;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+
+        let distance = edge2.dot(&q) * inv_det;
+        if distance < 0.0 {
+            return None;
+        }
+
+        Some((distance, [1.0 - u - v, u, v]))
+    }
+}
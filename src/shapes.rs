@@ -0,0 +1,639 @@
+//! Procedural primitives, centered on the origin with +y up.
+//!
+//! The `*_into` generators fill caller supplied buffers and work without an allocator, the
+//! `*_size` functions are const so the buffers can be sized at compile time. With the `alloc`
+//! feature every shape can also be built as an `OwnedGeometry`, including its line list.
+//!
+//! Faces are wound counter-clockwise seen from outside, face normals are computed from the
+//! winding.
+
+#[cfg(feature = "alloc")]
+use alloc::vec;
+use core::f32::consts;
+
+use nalgebra::Vector3;
+
+use crate::mesh::Geometry;
+#[cfg(feature = "alloc")]
+use crate::mesh::OwnedGeometry;
+
+/// Cube with a side of 2 and shared corners, ready to use without generating anything
+pub static CUBE: Geometry<'static> = Geometry {
+    vertices: &[
+        [-1.0, -1.0, -1.0],
+        [1.0, -1.0, -1.0],
+        [1.0, 1.0, -1.0],
+        [-1.0, 1.0, -1.0],
+        [-1.0, -1.0, 1.0],
+        [1.0, -1.0, 1.0],
+        [1.0, 1.0, 1.0],
+        [-1.0, 1.0, 1.0],
+    ],
+    faces: &[
+        [4, 5, 6],
+        [4, 6, 7],
+        [1, 0, 3],
+        [1, 3, 2],
+        [5, 1, 2],
+        [5, 2, 6],
+        [0, 4, 7],
+        [0, 7, 3],
+        [7, 6, 2],
+        [7, 2, 3],
+        [0, 1, 5],
+        [0, 5, 4],
+    ],
+    colors: &[],
+    lines: &[
+        [0, 1],
+        [1, 2],
+        [2, 3],
+        [3, 0],
+        [4, 5],
+        [5, 6],
+        [6, 7],
+        [7, 4],
+        [0, 4],
+        [1, 5],
+        [2, 6],
+        [3, 7],
+    ],
+    normals: &[
+        [0.0, 0.0, 1.0],
+        [0.0, 0.0, 1.0],
+        [0.0, 0.0, -1.0],
+        [0.0, 0.0, -1.0],
+        [1.0, 0.0, 0.0],
+        [1.0, 0.0, 0.0],
+        [-1.0, 0.0, 0.0],
+        [-1.0, 0.0, 0.0],
+        [0.0, 1.0, 0.0],
+        [0.0, 1.0, 0.0],
+        [0.0, -1.0, 0.0],
+        [0.0, -1.0, 0.0],
+    ],
+    vertex_normals: &[],
+    uvs: &[],
+    material_indices: &[],
+    bounds: None,
+};
+
+/// Number of elements a generator writes into each buffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ShapeSize {
+    pub vertices: usize,
+    pub faces: usize,
+    pub lines: usize,
+}
+
+/// Output buffers, each one must hold at least the matching `ShapeSize` count
+#[derive(Debug, Default)]
+pub struct ShapeBuffers<'b> {
+    pub vertices: &'b mut [[f32; 3]],
+    pub faces: &'b mut [[usize; 3]],
+    pub normals: &'b mut [[f32; 3]],
+    pub vertex_normals: &'b mut [[f32; 3]],
+    pub uvs: &'b mut [[f32; 2]],
+    // only used by the grid
+    pub lines: &'b mut [[usize; 2]],
+}
+
+impl ShapeBuffers<'_> {
+    fn fits(&self, size: ShapeSize) -> bool {
+        self.vertices.len() >= size.vertices
+            && self.vertex_normals.len() >= size.vertices
+            && self.uvs.len() >= size.vertices
+            && self.faces.len() >= size.faces
+            && self.normals.len() >= size.faces
+            && self.lines.len() >= size.lines
+    }
+}
+
+pub const fn cube_size() -> ShapeSize {
+    ShapeSize {
+        vertices: 24,
+        faces: 12,
+        lines: 0,
+    }
+}
+
+pub const fn uv_sphere_size(segments: usize, rings: usize) -> ShapeSize {
+    assert!(segments >= 3 && rings >= 2, "Sphere is too coarse");
+
+    ShapeSize {
+        vertices: (segments + 1) * (rings + 1),
+        faces: segments * (rings - 1) * 2,
+        lines: 0,
+    }
+}
+
+pub const fn cylinder_size(segments: usize) -> ShapeSize {
+    assert!(segments >= 3, "Cylinder is too coarse");
+
+    ShapeSize {
+        vertices: (segments + 1) * 4,
+        faces: segments * 4,
+        lines: 0,
+    }
+}
+
+pub const fn cone_size(segments: usize) -> ShapeSize {
+    assert!(segments >= 3, "Cone is too coarse");
+
+    ShapeSize {
+        vertices: segments * 3 + 2,
+        faces: segments * 2,
+        lines: 0,
+    }
+}
+
+pub const fn torus_size(segments: usize, sides: usize) -> ShapeSize {
+    assert!(segments >= 3 && sides >= 3, "Torus is too coarse");
+
+    ShapeSize {
+        vertices: (segments + 1) * (sides + 1),
+        faces: segments * sides * 2,
+        lines: 0,
+    }
+}
+
+pub const fn plane_size(subdivisions_x: usize, subdivisions_z: usize) -> ShapeSize {
+    assert!(
+        subdivisions_x >= 1 && subdivisions_z >= 1,
+        "Plane needs at least one subdivision"
+    );
+
+    ShapeSize {
+        vertices: (subdivisions_x + 1) * (subdivisions_z + 1),
+        faces: subdivisions_x * subdivisions_z * 2,
+        lines: 0,
+    }
+}
+
+pub const fn grid_size(divisions: usize) -> ShapeSize {
+    assert!(divisions >= 1, "Grid needs at least one division");
+
+    ShapeSize {
+        vertices: (divisions + 1) * 4,
+        faces: 0,
+        lines: (divisions + 1) * 2,
+    }
+}
+
+/// Cube with separate vertices per side, so it has flat vertex normals and per-side uvs
+pub fn cube_into(size: f32, buffers: ShapeBuffers<'_>) -> Option<Geometry<'_>> {
+    let mut builder = Builder::new(buffers, cube_size())?;
+    let half = size / 2.0;
+
+    // normal, then two axes along the side with u x v = normal
+    let sides = [
+        (Vector3::x(), -Vector3::z(), Vector3::y()),
+        (-Vector3::x(), Vector3::z(), Vector3::y()),
+        (Vector3::y(), Vector3::x(), -Vector3::z()),
+        (-Vector3::y(), Vector3::x(), Vector3::z()),
+        (Vector3::z(), Vector3::x(), Vector3::y()),
+        (-Vector3::z(), -Vector3::x(), Vector3::y()),
+    ];
+
+    for (normal, u, v) in sides {
+        let corner = |su: f32, sv: f32| (normal + u * su + v * sv) * half;
+
+        let a = builder.vertex(corner(-1.0, -1.0), normal, [0.0, 1.0]);
+        let b = builder.vertex(corner(1.0, -1.0), normal, [1.0, 1.0]);
+        let c = builder.vertex(corner(1.0, 1.0), normal, [1.0, 0.0]);
+        let d = builder.vertex(corner(-1.0, 1.0), normal, [0.0, 0.0]);
+
+        builder.quad(a, b, c, d);
+    }
+
+    Some(builder.finish())
+}
+
+/// `segments` around the y axis and `rings` from pole to pole
+pub fn uv_sphere_into(
+    radius: f32,
+    segments: usize,
+    rings: usize,
+    buffers: ShapeBuffers<'_>,
+) -> Option<Geometry<'_>> {
+    let mut builder = Builder::new(buffers, uv_sphere_size(segments, rings))?;
+
+    for ring in 0..=rings {
+        let (sin_phi, cos_phi) = libm::sincosf(consts::PI * ring as f32 / rings as f32);
+
+        for segment in 0..=segments {
+            let (sin_theta, cos_theta) =
+                libm::sincosf(consts::TAU * segment as f32 / segments as f32);
+            let normal = Vector3::new(sin_phi * sin_theta, cos_phi, sin_phi * cos_theta);
+
+            builder.vertex(
+                normal * radius,
+                normal,
+                [segment as f32 / segments as f32, ring as f32 / rings as f32],
+            );
+        }
+    }
+
+    let index = |ring: usize, segment: usize| ring * (segments + 1) + segment;
+
+    for ring in 0..rings {
+        for segment in 0..segments {
+            let a = index(ring, segment);
+            let b = index(ring + 1, segment);
+            let c = index(ring + 1, segment + 1);
+            let d = index(ring, segment + 1);
+
+            // the quads touching the poles collapse into a single triangle
+            if ring != rings - 1 {
+                builder.face(a, b, c);
+            }
+            if ring != 0 {
+                builder.face(a, c, d);
+            }
+        }
+    }
+
+    Some(builder.finish())
+}
+
+/// Capped cylinder along the y axis
+pub fn cylinder_into(
+    radius: f32,
+    height: f32,
+    segments: usize,
+    buffers: ShapeBuffers<'_>,
+) -> Option<Geometry<'_>> {
+    let mut builder = Builder::new(buffers, cylinder_size(segments))?;
+    let half = height / 2.0;
+
+    let side = builder.vertex_count;
+    for segment in 0..=segments {
+        let (sin, cos) = libm::sincosf(consts::TAU * segment as f32 / segments as f32);
+        let normal = Vector3::new(sin, 0.0, cos);
+        let u = segment as f32 / segments as f32;
+
+        builder.vertex(normal * radius - Vector3::y() * half, normal, [u, 1.0]);
+        builder.vertex(normal * radius + Vector3::y() * half, normal, [u, 0.0]);
+    }
+
+    for segment in 0..segments {
+        let bottom = side + segment * 2;
+
+        builder.quad(bottom, bottom + 2, bottom + 3, bottom + 1);
+    }
+
+    builder.cap(radius, half, segments, true);
+    builder.cap(radius, -half, segments, false);
+
+    Some(builder.finish())
+}
+
+/// Cone along the y axis with the apex on top
+pub fn cone_into(
+    radius: f32,
+    height: f32,
+    segments: usize,
+    buffers: ShapeBuffers<'_>,
+) -> Option<Geometry<'_>> {
+    let mut builder = Builder::new(buffers, cone_size(segments))?;
+    let half = height / 2.0;
+
+    // the side normal leans up by the slope of the cone
+    let side_normal = |angle: f32| {
+        let (sin, cos) = libm::sincosf(angle);
+        Vector3::new(sin * height, radius, cos * height).normalize()
+    };
+
+    let ring = builder.vertex_count;
+    for segment in 0..=segments {
+        let angle = consts::TAU * segment as f32 / segments as f32;
+        let (sin, cos) = libm::sincosf(angle);
+
+        builder.vertex(
+            Vector3::new(sin * radius, -half, cos * radius),
+            side_normal(angle),
+            [segment as f32 / segments as f32, 1.0],
+        );
+    }
+
+    // one apex per segment, so each gets the normal of its own slice
+    for segment in 0..segments {
+        let angle = consts::TAU * (segment as f32 + 0.5) / segments as f32;
+
+        let apex = builder.vertex(
+            Vector3::y() * half,
+            side_normal(angle),
+            [(segment as f32 + 0.5) / segments as f32, 0.0],
+        );
+
+        builder.face(ring + segment, ring + segment + 1, apex);
+    }
+
+    builder.cap(radius, -half, segments, false);
+
+    Some(builder.finish())
+}
+
+/// Torus around the y axis, `segments` around the ring and `sides` around the tube
+pub fn torus_into(
+    major_radius: f32,
+    minor_radius: f32,
+    segments: usize,
+    sides: usize,
+    buffers: ShapeBuffers<'_>,
+) -> Option<Geometry<'_>> {
+    let mut builder = Builder::new(buffers, torus_size(segments, sides))?;
+
+    for segment in 0..=segments {
+        let (sin_theta, cos_theta) = libm::sincosf(consts::TAU * segment as f32 / segments as f32);
+
+        for side in 0..=sides {
+            let (sin_phi, cos_phi) = libm::sincosf(consts::TAU * side as f32 / sides as f32);
+
+            let normal = Vector3::new(cos_phi * sin_theta, sin_phi, cos_phi * cos_theta);
+            let center = Vector3::new(sin_theta, 0.0, cos_theta) * major_radius;
+
+            builder.vertex(
+                center + normal * minor_radius,
+                normal,
+                [segment as f32 / segments as f32, side as f32 / sides as f32],
+            );
+        }
+    }
+
+    let index = |segment: usize, side: usize| segment * (sides + 1) + side;
+
+    for segment in 0..segments {
+        for side in 0..sides {
+            builder.quad(
+                index(segment, side),
+                index(segment + 1, side),
+                index(segment + 1, side + 1),
+                index(segment, side + 1),
+            );
+        }
+    }
+
+    Some(builder.finish())
+}
+
+/// Flat plane on the xz plane facing +y
+pub fn plane_into(
+    width: f32,
+    depth: f32,
+    subdivisions_x: usize,
+    subdivisions_z: usize,
+    buffers: ShapeBuffers<'_>,
+) -> Option<Geometry<'_>> {
+    let mut builder = Builder::new(buffers, plane_size(subdivisions_x, subdivisions_z))?;
+
+    for z in 0..=subdivisions_z {
+        let v = z as f32 / subdivisions_z as f32;
+
+        for x in 0..=subdivisions_x {
+            let u = x as f32 / subdivisions_x as f32;
+
+            builder.vertex(
+                Vector3::new((u - 0.5) * width, 0.0, (v - 0.5) * depth),
+                Vector3::y(),
+                [u, v],
+            );
+        }
+    }
+
+    let index = |x: usize, z: usize| z * (subdivisions_x + 1) + x;
+
+    for z in 0..subdivisions_z {
+        for x in 0..subdivisions_x {
+            builder.quad(
+                index(x, z),
+                index(x, z + 1),
+                index(x + 1, z + 1),
+                index(x + 1, z),
+            );
+        }
+    }
+
+    Some(builder.finish())
+}
+
+/// Square line grid on the xz plane, for wireframe ground planes
+pub fn grid_into(size: f32, divisions: usize, buffers: ShapeBuffers<'_>) -> Option<Geometry<'_>> {
+    let mut builder = Builder::new(buffers, grid_size(divisions))?;
+    let half = size / 2.0;
+
+    for i in 0..=divisions {
+        let offset = size * i as f32 / divisions as f32 - half;
+
+        let a = builder.vertex(Vector3::new(offset, 0.0, -half), Vector3::y(), [0.0; 2]);
+        let b = builder.vertex(Vector3::new(offset, 0.0, half), Vector3::y(), [0.0; 2]);
+        builder.line(a, b);
+
+        let a = builder.vertex(Vector3::new(-half, 0.0, offset), Vector3::y(), [0.0; 2]);
+        let b = builder.vertex(Vector3::new(half, 0.0, offset), Vector3::y(), [0.0; 2]);
+        builder.line(a, b);
+    }
+
+    Some(builder.finish())
+}
+
+#[cfg(feature = "alloc")]
+pub fn cube(size: f32) -> OwnedGeometry {
+    build_owned(cube_size(), |buffers| cube_into(size, buffers))
+}
+
+#[cfg(feature = "alloc")]
+pub fn uv_sphere(radius: f32, segments: usize, rings: usize) -> OwnedGeometry {
+    build_owned(uv_sphere_size(segments, rings), |buffers| {
+        uv_sphere_into(radius, segments, rings, buffers)
+    })
+}
+
+#[cfg(feature = "alloc")]
+pub fn cylinder(radius: f32, height: f32, segments: usize) -> OwnedGeometry {
+    build_owned(cylinder_size(segments), |buffers| {
+        cylinder_into(radius, height, segments, buffers)
+    })
+}
+
+#[cfg(feature = "alloc")]
+pub fn cone(radius: f32, height: f32, segments: usize) -> OwnedGeometry {
+    build_owned(cone_size(segments), |buffers| {
+        cone_into(radius, height, segments, buffers)
+    })
+}
+
+#[cfg(feature = "alloc")]
+pub fn torus(major_radius: f32, minor_radius: f32, segments: usize, sides: usize) -> OwnedGeometry {
+    build_owned(torus_size(segments, sides), |buffers| {
+        torus_into(major_radius, minor_radius, segments, sides, buffers)
+    })
+}
+
+#[cfg(feature = "alloc")]
+pub fn plane(
+    width: f32,
+    depth: f32,
+    subdivisions_x: usize,
+    subdivisions_z: usize,
+) -> OwnedGeometry {
+    build_owned(plane_size(subdivisions_x, subdivisions_z), |buffers| {
+        plane_into(width, depth, subdivisions_x, subdivisions_z, buffers)
+    })
+}
+
+#[cfg(feature = "alloc")]
+pub fn grid(size: f32, divisions: usize) -> OwnedGeometry {
+    build_owned(grid_size(divisions), |buffers| {
+        grid_into(size, divisions, buffers)
+    })
+}
+
+#[cfg(feature = "alloc")]
+fn build_owned<F>(size: ShapeSize, fill: F) -> OwnedGeometry
+where
+    F: FnOnce(ShapeBuffers<'_>) -> Option<Geometry<'_>>,
+{
+    let mut geometry = OwnedGeometry {
+        vertices: vec![[0.0; 3]; size.vertices],
+        faces: vec![[0; 3]; size.faces],
+        lines: vec![[0; 2]; size.lines],
+        normals: vec![[0.0; 3]; size.faces],
+        vertex_normals: vec![[0.0; 3]; size.vertices],
+        uvs: vec![[0.0; 2]; size.vertices],
+        ..Default::default()
+    };
+
+    let filled = fill(ShapeBuffers {
+        vertices: &mut geometry.vertices,
+        faces: &mut geometry.faces,
+        normals: &mut geometry.normals,
+        vertex_normals: &mut geometry.vertex_normals,
+        uvs: &mut geometry.uvs,
+        lines: &mut geometry.lines,
+    });
+    debug_assert!(filled.is_some());
+
+    if geometry.lines.is_empty() {
+        geometry.generate_lines();
+    }
+    geometry.update_bounds();
+
+    geometry
+}
+
+struct Builder<'b> {
+    buffers: ShapeBuffers<'b>,
+    vertex_count: usize,
+    face_count: usize,
+    line_count: usize,
+}
+
+impl<'b> Builder<'b> {
+    fn new(buffers: ShapeBuffers<'b>, size: ShapeSize) -> Option<Builder<'b>> {
+        if !buffers.fits(size) {
+            return None;
+        }
+
+        Some(Builder {
+            buffers,
+            vertex_count: 0,
+            face_count: 0,
+            line_count: 0,
+        })
+    }
+
+    fn vertex(&mut self, position: Vector3<f32>, normal: Vector3<f32>, uv: [f32; 2]) -> usize {
+        let index = self.vertex_count;
+
+        self.buffers.vertices[index] = position.into();
+        self.buffers.vertex_normals[index] = normal.into();
+        self.buffers.uvs[index] = uv;
+        self.vertex_count += 1;
+
+        index
+    }
+
+    fn face(&mut self, a: usize, b: usize, c: usize) {
+        self.buffers.faces[self.face_count] = [a, b, c];
+        self.face_count += 1;
+    }
+
+    // corners in counter-clockwise order
+    fn quad(&mut self, a: usize, b: usize, c: usize, d: usize) {
+        self.face(a, b, c);
+        self.face(a, c, d);
+    }
+
+    fn line(&mut self, a: usize, b: usize) {
+        self.buffers.lines[self.line_count] = [a, b];
+        self.line_count += 1;
+    }
+
+    // flat disc at height `y`, facing up or down
+    fn cap(&mut self, radius: f32, y: f32, segments: usize, up: bool) {
+        let normal = if up { Vector3::y() } else { -Vector3::y() };
+
+        let center = self.vertex(Vector3::y() * y, normal, [0.5, 0.5]);
+        for segment in 0..segments {
+            let (sin, cos) = libm::sincosf(consts::TAU * segment as f32 / segments as f32);
+
+            self.vertex(
+                Vector3::new(sin * radius, y, cos * radius),
+                normal,
+                [0.5 + sin * 0.5, 0.5 + cos * 0.5],
+            );
+        }
+
+        for segment in 0..segments {
+            let current = center + 1 + segment;
+            let next = center + 1 + (segment + 1) % segments;
+
+            if up {
+                self.face(center, current, next);
+            } else {
+                self.face(center, next, current);
+            }
+        }
+    }
+
+    fn finish(self) -> Geometry<'b> {
+        let ShapeBuffers {
+            vertices,
+            faces,
+            normals,
+            vertex_normals,
+            uvs,
+            lines,
+        } = self.buffers;
+
+        for (face, normal) in faces[..self.face_count].iter().zip(normals.iter_mut()) {
+            let [a, b, c] = face.map(|v| Vector3::from(vertices[v]));
+
+            *normal = (b - a)
+                .cross(&(c - a))
+                .try_normalize(f32::EPSILON)
+                .unwrap_or_else(Vector3::zeros)
+                .into();
+        }
+
+        let vertices: &'b [[f32; 3]] = vertices;
+        let faces: &'b [[usize; 3]] = faces;
+        let normals: &'b [[f32; 3]] = normals;
+        let vertex_normals: &'b [[f32; 3]] = vertex_normals;
+        let uvs: &'b [[f32; 2]] = uvs;
+        let lines: &'b [[usize; 2]] = lines;
+
+        Geometry {
+            vertices: &vertices[..self.vertex_count],
+            faces: &faces[..self.face_count],
+            colors: &[],
+            lines: &lines[..self.line_count],
+            normals: &normals[..self.face_count],
+            vertex_normals: &vertex_normals[..self.vertex_count],
+            uvs: &uvs[..self.vertex_count],
+            material_indices: &[],
+            bounds: None,
+        }
+    }
+}
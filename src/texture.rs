@@ -0,0 +1,79 @@
+use embedded_graphics_core::pixelcolor::Rgb565;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TextureData<'a> {
+    Rgb565(&'a [Rgb565]),
+    Indexed {
+        indices: &'a [u8],
+        palette: &'a [Rgb565],
+    },
+}
+
+/// A row-major image, borrowed so that it can live in flash.
+///
+/// Texture coordinates wrap around, (0, 0) is the top left corner of the image.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Texture<'a> {
+    data: TextureData<'a>,
+    width: u16,
+    height: u16,
+}
+
+impl<'a> Texture<'a> {
+    pub const fn new(data: &'a [Rgb565], width: u16, height: u16) -> Texture<'a> {
+        assert!(width > 0 && height > 0, "Texture must not be empty");
+        assert!(
+            data.len() == width as usize * height as usize,
+            "Texture data does not match its size"
+        );
+
+        Texture {
+            data: TextureData::Rgb565(data),
+            width,
+            height,
+        }
+    }
+
+    pub const fn new_indexed(
+        indices: &'a [u8],
+        palette: &'a [Rgb565],
+        width: u16,
+        height: u16,
+    ) -> Texture<'a> {
+        assert!(width > 0 && height > 0, "Texture must not be empty");
+        assert!(
+            indices.len() == width as usize * height as usize,
+            "Texture data does not match its size"
+        );
+        assert!(!palette.is_empty(), "Palette is empty");
+
+        Texture {
+            data: TextureData::Indexed { indices, palette },
+            width,
+            height,
+        }
+    }
+
+    pub fn width(&self) -> u16 {
+        self.width
+    }
+
+    pub fn height(&self) -> u16 {
+        self.height
+    }
+
+    /// Nearest-neighbour lookup, `x` and `y` are in texels
+    #[inline]
+    pub fn sample(&self, x: f32, y: f32) -> Rgb565 {
+        let x = (libm::floorf(x) as i32).rem_euclid(self.width as i32) as usize;
+        let y = (libm::floorf(y) as i32).rem_euclid(self.height as i32) as usize;
+        let index = y * self.width as usize + x;
+
+        match self.data {
+            TextureData::Rgb565(data) => data[index],
+            TextureData::Indexed { indices, palette } => {
+                palette[indices[index] as usize % palette.len()]
+            }
+        }
+    }
+}